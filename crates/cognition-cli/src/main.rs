@@ -1,54 +1,172 @@
+use clap::{Parser, Subcommand, ValueEnum};
 use cognition::{
-    run_decision, tools, CognitionError, Decision, DecisionPromptTemplate, DecisionState,
+    graph, lint_file, lint_template, run_decision, tool_ids, tools, tree, validate_file,
+    CognitionConfig, CognitionError, CompiledTree, DecisionPromptTemplate, DecisionState,
+    Diagnostic, Session,
 };
 use std::fs::File;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(about = "Cognitive decision-making system")]
+struct Cli {
+    /// Configuration YAML file; without one, the configuration is read from environment variables
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the interactive decision loop (default)
+    Run {
+        /// Decision tree YAML file [default: paths.tree]
+        #[arg(long)]
+        tree: Option<PathBuf>,
+        /// Decision prompt template YAML file [default: paths.template]
+        #[arg(long)]
+        template: Option<PathBuf>,
+    },
+    /// Check a decision tree for structural errors
+    Validate {
+        /// Decision tree YAML file
+        tree: PathBuf,
+    },
+    /// Check a decision tree, its prompt template and its choices
+    Lint {
+        /// Decision tree YAML file
+        tree: PathBuf,
+        /// Decision prompt template YAML file [default: paths.template]
+        #[arg(long)]
+        template: Option<PathBuf>,
+    },
+    /// Render a decision tree as a graph
+    Graph {
+        /// Decision tree YAML file
+        tree: PathBuf,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+        /// Write the graph to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), CognitionError> {
-    let decision_prompt_template = {
-        let mut file = File::open("decision_prompt_template.yaml").unwrap();
-        let mut decision_prompt_template = String::new();
-        file.read_to_string(&mut decision_prompt_template).unwrap();
-        DecisionPromptTemplate::new(decision_prompt_template)
+async fn main() -> Result<ExitCode, CognitionError> {
+    // Logs go to stderr, filtered by `RUST_LOG`
+    env_logger::init();
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => CognitionConfig::load(path)?,
+        None => CognitionConfig::from_env(),
     };
 
-    let decision_nodes = {
-        // Load the YAML file containing decision nodes
-        let mut file = File::open("decision_tree.yaml").unwrap();
-        let mut decision_nodes = String::new();
-        file.read_to_string(&mut decision_nodes).unwrap();
+    match cli.command.unwrap_or(Command::Run {
+        tree: None,
+        template: None,
+    }) {
+        Command::Run { tree, template } => {
+            let tree = tree.unwrap_or_else(|| config.paths.tree.clone());
+            let template = template.unwrap_or_else(|| config.paths.template.clone());
+            run(&config, &tree, &template).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Validate { tree } => {
+            let (tool_ids, mut diagnostics) = tool_ids(&config.paths.tools);
+            let tool_ids: Vec<&str> = tool_ids.iter().map(String::as_str).collect();
+            diagnostics.extend(validate_file(&tree, &tool_ids));
+            Ok(exit_code(report(&tree, &diagnostics)))
+        }
+        Command::Lint { tree, template } => {
+            let template = template.unwrap_or_else(|| config.paths.template.clone());
+            let (tool_ids, mut diagnostics) = tool_ids(&config.paths.tools);
+            let tool_ids: Vec<&str> = tool_ids.iter().map(String::as_str).collect();
+            diagnostics.extend(lint_file(&tree, &tool_ids));
+            let tree_ok = report(&tree, &diagnostics);
+            let decision_prompt_template = DecisionPromptTemplate::new(read_file(&template)?);
+            let template_ok = report(&template, &lint_template(&decision_prompt_template));
+            Ok(exit_code(tree_ok && template_ok))
+        }
+        Command::Graph {
+            tree,
+            format,
+            output,
+        } => {
+            let decision_nodes = tree::load(&tree)?;
+            let graph = match format {
+                GraphFormat::Dot => graph::to_dot(&decision_nodes),
+                GraphFormat::Mermaid => graph::to_mermaid(&decision_nodes),
+            };
+            match output {
+                Some(output) => std::fs::write(&output, graph).map_err(|err| {
+                    CognitionError(format!("Unable to write {}: {}", output.display(), err))
+                })?,
+                None => print!("{}", graph),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
 
-        let decision_nodes: Vec<Decision> = serde_yaml::from_str(&decision_nodes).unwrap();
-        decision_nodes
-    };
+fn read_file(path: &Path) -> Result<String, CognitionError> {
+    let mut file = File::open(path)
+        .map_err(|err| CognitionError(format!("Unable to open {}: {}", path.display(), err)))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|err| CognitionError(format!("Unable to read {}: {}", path.display(), err)))?;
+    Ok(content)
+}
 
-    let config = format!(
-        r#"
-    models:
-      davinci003:
-        api_key: {}
-    "#,
-        std::env::var("OPENAI_API_KEY").unwrap(),
+// Print diagnostics, returning false if any of them is an error
+fn report(path: &Path, diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        println!("{}: {}", path.display(), diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    println!(
+        "{}: {} error(s), {} warning(s)",
+        path.display(),
+        errors,
+        warnings
     );
+    errors == 0
+}
+
+fn exit_code(ok: bool) -> ExitCode {
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
-    let wolfram_alpha = Box::new(tools::WolframAlpha::new(
-        std::env::var("WOLFRAM_APP_ID").unwrap(),
-    ));
+async fn run(config: &CognitionConfig, tree: &Path, template: &Path) -> Result<(), CognitionError> {
+    let decision_prompt_template = DecisionPromptTemplate::new(read_file(template)?);
 
-    let signal_book = Box::new(tools::Signal {
-        id: "signal_book".into(),
-        name: "Signal Book".into(),
-        description: "Signal Book".into(),
-        signal: "Beep!".into(),
-    });
+    let decision_tree = CompiledTree::load(tree)?;
 
-    let mut state = DecisionState::new(&config, decision_prompt_template, decision_nodes);
-    state.add_tool(wolfram_alpha);
-    state.add_tool(signal_book);
+    let mut state = DecisionState::new(config, decision_prompt_template, decision_tree)?;
+    for tool in tools::load_tools(config)? {
+        state.add_tool(tool);
+    }
 
+    let mut session = Session::new();
     let mut user_input = None;
-    while let Some(result) = run_decision(user_input, &mut state).await? {
+    while let Some(result) = run_decision(user_input, &state, &mut session).await? {
         // Print decision prompt, if any
         if let Some(decision_prompt) = result.decision_prompt {
             println!("\n++++++ PROMPT ++++++");
@@ -70,21 +188,42 @@ async fn main() -> Result<(), CognitionError> {
         if !result.predictions.is_empty() {
             println!("\nPREDICTIONS:");
             for prediction in result.predictions {
-                println!("  [✓] {}: {}", prediction.id, prediction.choice);
+                match prediction.choice_match {
+                    Some(choice_match) => println!(
+                        "  [✓] {}: {} ({} {:.2})",
+                        prediction.id, prediction.choice, choice_match.strategy, choice_match.score
+                    ),
+                    None => println!("  [✓] {}: {}", prediction.id, prediction.choice),
+                }
+            }
+        }
+
+        // Print why the decision did or did not advance
+        if result.user_input.is_some() {
+            println!("\nOUTCOME: {}", result.outcome);
+            if let Some(prediction_stop) = result.prediction_stop {
+                println!("PREDICTION STOPPED: {}", prediction_stop);
             }
         }
 
         // Display the current decision text and choices
-        println!(
-            "\nDECISION: {}: {}",
-            result.decision_node.id, result.decision_node.text
-        );
-        println!("\n{}: {}", state.agent, result.decision_node.text);
-        for choice in result.decision_node.choices() {
-            println!("- {}", choice.text);
+        println!("\nDECISION: {}: {}", result.decision_node.id, result.text);
+        if let Some(clarification) = &result.clarification {
+            println!("\n{}: {}", state.agent, clarification);
+        }
+        println!("\n{}: {}", state.agent, result.text);
+        for choice in &result.choices {
+            println!("- {}", choice);
         }
 
-        if result.decision_node.choices().len() == 0 {
+        // The end of a sub-tree waits for an answer before returning to the caller, while a
+        // node whose guards hide every choice, without an `else` or slots the answer can fill,
+        // cannot move on at all
+        let node = &result.decision_node;
+        let finished = node.is_terminal() && session.call_stack.is_empty();
+        let has_slots = node.slots.as_ref().is_some_and(|slots| !slots.is_empty());
+        let stuck = result.choices.is_empty() && node.else_id.is_none() && !has_slots;
+        if finished || (stuck && !node.is_terminal()) {
             println!("\n[!] No choices available. Exiting.");
             break;
         }
@@ -0,0 +1,224 @@
+use crate::{
+    error::ApiError,
+    sessions::{SessionInfo, Sessions},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use cognition::{DecisionResult, Session};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TREE: &str = "decision_tree";
+
+// Saved sessions are only accepted by `/sessions/restore`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateSession {
+    tree: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreSession {
+    tree: Option<String>,
+    /// Saved session state to continue from.
+    session: Session,
+}
+
+#[derive(Serialize)]
+pub struct CreatedSession {
+    session: SessionInfo,
+    result: DecisionResult,
+}
+
+#[derive(Deserialize)]
+pub struct Input {
+    input: String,
+}
+
+pub fn router(sessions: Sessions) -> Router {
+    Router::new()
+        .route("/sessions", post(create_session))
+        .route("/sessions/restore", post(restore_session))
+        .route("/sessions/:id", get(get_session).delete(delete_session))
+        .route("/sessions/:id/input", post(session_input))
+        .with_state(sessions)
+}
+
+async fn create_session(
+    State(sessions): State<Sessions>,
+    Json(request): Json<CreateSession>,
+) -> Result<(StatusCode, Json<CreatedSession>), ApiError> {
+    let tree = request.tree.unwrap_or_else(|| DEFAULT_TREE.into());
+    let (session, result) = sessions.create(tree).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedSession { session, result }),
+    ))
+}
+
+async fn restore_session(
+    State(sessions): State<Sessions>,
+    Json(request): Json<RestoreSession>,
+) -> Result<(StatusCode, Json<CreatedSession>), ApiError> {
+    let tree = request.tree.unwrap_or_else(|| DEFAULT_TREE.into());
+    let (session, result) = sessions.restore(tree, request.session).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedSession { session, result }),
+    ))
+}
+
+async fn session_input(
+    State(sessions): State<Sessions>,
+    Path(id): Path<String>,
+    Json(request): Json<Input>,
+) -> Result<Json<DecisionResult>, ApiError> {
+    Ok(Json(sessions.input(&id, request.input).await?))
+}
+
+async fn get_session(
+    State(sessions): State<Sessions>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionInfo>, ApiError> {
+    Ok(Json(sessions.get(&id).await?))
+}
+
+async fn delete_session(
+    State(sessions): State<Sessions>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    sessions.delete(&id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::Settings;
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use cognition::CognitionConfig;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    const TREE: &str = r#"
+- id: start
+  text: "Buy or ask?"
+  choices:
+    - {choice: "Buy", next_id: buy}
+    - {choice: "Ask", next_id: ask}
+- id: buy
+  text: "Bought"
+  choices: []
+- id: ask
+  text: "Asked"
+  choices: []
+"#;
+
+    const TEMPLATE: &str = "decision: {{decision_prompt}}\nchoices: {{choices}}\n\
+        response: {{user_input}}\nchoice:";
+
+    // A router on a directory holding a `decision_tree` tree, answered by the mock model
+    fn app(name: &str, allow_restore: bool) -> Router {
+        let dir = std::env::temp_dir().join(format!("cognition_server_test_{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("decision_tree.yaml"), TREE).unwrap();
+        std::fs::write(dir.join("template.yaml"), TEMPLATE).unwrap();
+        let config = CognitionConfig::from_yaml(
+            "models: {default: mock, mock: {fallback: Buy}}\npaths: {tools: []}",
+        )
+        .unwrap();
+        router(Sessions::new(Settings {
+            trees: dir.clone(),
+            template: dir.join("template.yaml"),
+            allow_restore,
+            config,
+        }))
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn creates_answers_gets_and_deletes_a_session() {
+        let app = app("lifecycle", false);
+
+        let (status, created) = send(&app, Method::POST, "/sessions", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["session"]["tree"], "decision_tree");
+        assert_eq!(created["session"]["choices"], json!(["Buy", "Ask"]));
+        let id = created["session"]["id"].as_str().unwrap().to_owned();
+        let uri = format!("/sessions/{}", id);
+
+        let input = Some(json!({"input": "I'd like to buy"}));
+        let (status, result) = send(&app, Method::POST, &format!("{}/input", uri), input).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["choice"], "Buy");
+        assert_eq!(result["current_id"], "buy");
+
+        let (status, info) = send(&app, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(info["session"]["current_id"], "buy");
+        assert_eq!(info["text"], "Bought");
+
+        let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, error) = send(&app, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["error"], format!("Session '{}' not found", id));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_trees_and_session_fields() {
+        let app = app("invalid", false);
+        let tree = Some(json!({"tree": "missing"}));
+        let (status, _) = send(&app, Method::POST, "/sessions", tree).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let tree = Some(json!({"tree": "../decision_tree"}));
+        let (status, _) = send(&app, Method::POST, "/sessions", tree).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let session = Some(json!({"session": {"current_id": "buy", "history": ""}}));
+        let (status, _) = send(&app, Method::POST, "/sessions", session).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn restores_sessions_only_when_allowed() {
+        let saved = Some(json!({"session": {"current_id": "buy", "history": ""}}));
+        let app = app("restore_forbidden", false);
+        let (status, error) = send(&app, Method::POST, "/sessions/restore", saved.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(error["error"].as_str().unwrap().contains("--allow-restore"));
+
+        let app = self::app("restore_allowed", true);
+        let (status, created) = send(&app, Method::POST, "/sessions/restore", saved).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["session"]["session"]["current_id"], "buy");
+        let unknown = Some(json!({"session": {"current_id": "missing", "history": ""}}));
+        let (status, _) = send(&app, Method::POST, "/sessions/restore", unknown).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
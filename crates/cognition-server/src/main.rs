@@ -1,3 +1,59 @@
-fn main() {
-    println!("Hello, world!");
+use clap::Parser;
+use cognition::{CognitionConfig, CognitionError};
+use log::info;
+use std::{net::SocketAddr, path::PathBuf};
+
+mod api;
+mod error;
+mod sessions;
+
+#[derive(Parser)]
+#[command(about = "HTTP API for the cognitive decision-making system")]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    addr: SocketAddr,
+    /// Configuration YAML file; without one, the configuration is read from environment variables
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Directory containing the decision trees, looked up as `<name>.yaml` [default: paths.trees]
+    #[arg(long)]
+    trees: Option<PathBuf>,
+    /// Decision prompt template YAML file [default: paths.template]
+    #[arg(long)]
+    template: Option<PathBuf>,
+    /// Accept saved sessions at `POST /sessions/restore`. Clients then choose the variables and
+    /// tool responses of their sessions, so only enable it for trusted clients
+    #[arg(long)]
+    allow_restore: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), CognitionError> {
+    // Logs at `info` and above unless `RUST_LOG` says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let config = match &args.config {
+        Some(path) => CognitionConfig::load(path)?,
+        None => CognitionConfig::from_env(),
+    };
+
+    let sessions = sessions::Sessions::new(sessions::Settings {
+        trees: args.trees.unwrap_or_else(|| config.paths.trees.clone()),
+        template: args
+            .template
+            .unwrap_or_else(|| config.paths.template.clone()),
+        allow_restore: args.allow_restore,
+        config,
+    });
+
+    let io_error = |err: std::io::Error| CognitionError(format!("Server error: {}", err));
+    let listener = tokio::net::TcpListener::bind(args.addr)
+        .await
+        .map_err(io_error)?;
+    info!("Listening on {}", args.addr);
+    axum::serve(listener, api::router(sessions))
+        .await
+        .map_err(io_error)
 }
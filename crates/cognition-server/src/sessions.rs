@@ -0,0 +1,222 @@
+use crate::error::ApiError;
+use cognition::{
+    run_decision, tools, CognitionConfig, CompiledTree, Decision, DecisionPromptTemplate,
+    DecisionResult, DecisionState, Session,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, MutexGuard},
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Where trees and the prompt template are loaded from, and the model configuration.
+pub struct Settings {
+    pub trees: PathBuf,
+    pub template: PathBuf,
+    /// Whether clients may restore saved sessions.
+    pub allow_restore: bool,
+    pub config: CognitionConfig,
+}
+
+/// Snapshot of a session: its tree, current node and serialized state.
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub tree: String,
+    pub decision_node: Decision,
+    /// Text of the current node, with tool responses filled in.
+    pub text: String,
+    /// Choices of the current node whose guard holds.
+    pub choices: Vec<String>,
+    pub session: Session,
+}
+
+/// Shared store of sessions.
+///
+/// Each tree is loaded once into a `DecisionState` shared by all of its sessions, and
+/// each session is locked only while a decision runs on it.
+#[derive(Clone)]
+pub struct Sessions {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    settings: Settings,
+    states: std::sync::Mutex<HashMap<String, Arc<DecisionState>>>,
+    sessions: std::sync::Mutex<HashMap<String, Arc<Mutex<Conversation>>>>,
+}
+
+// A session together with the tree it runs on
+struct Conversation {
+    tree: String,
+    state: Arc<DecisionState>,
+    session: Session,
+}
+
+impl Conversation {
+    fn info(&self, id: &str) -> Result<SessionInfo, ApiError> {
+        let decision_node = self.state.current_node(&self.session)?.clone();
+        Ok(SessionInfo {
+            id: id.to_owned(),
+            tree: self.tree.clone(),
+            text: self.session.render(&decision_node.text),
+            choices: decision_node
+                .choices(&self.session.context())
+                .iter()
+                .map(|choice| choice.text.clone())
+                .collect(),
+            decision_node,
+            session: self.session.clone(),
+        })
+    }
+}
+
+impl Sessions {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                settings,
+                states: Default::default(),
+                sessions: Default::default(),
+            }),
+        }
+    }
+
+    /// Starts a session on the named tree and runs its first decision.
+    pub async fn create(&self, tree: String) -> Result<(SessionInfo, DecisionResult), ApiError> {
+        let state = self.state(&tree)?;
+        self.start(tree, state, Session::new()).await
+    }
+
+    /// Restores a saved session on the named tree, if the server trusts its clients with it.
+    pub async fn restore(
+        &self,
+        tree: String,
+        session: Session,
+    ) -> Result<(SessionInfo, DecisionResult), ApiError> {
+        if !self.inner.settings.allow_restore {
+            return Err(ApiError::Forbidden(
+                "Restoring sessions is disabled; start the server with --allow-restore".into(),
+            ));
+        }
+        let state = self.state(&tree)?;
+        state
+            .check_session(&session)
+            .map_err(|err| ApiError::BadRequest(err.0))?;
+        self.start(tree, state, session).await
+    }
+
+    // Registers a session and runs its first decision
+    async fn start(
+        &self,
+        tree: String,
+        state: Arc<DecisionState>,
+        mut session: Session,
+    ) -> Result<(SessionInfo, DecisionResult), ApiError> {
+        let result = run_decision(None, &state, &mut session)
+            .await?
+            .ok_or_else(|| ApiError::Internal("No decision result".into()))?;
+
+        let id = Uuid::new_v4().to_string();
+        let conversation = Conversation {
+            tree,
+            state,
+            session,
+        };
+        let info = conversation.info(&id)?;
+        lock(&self.inner.sessions).insert(id, Arc::new(Mutex::new(conversation)));
+        Ok((info, result))
+    }
+
+    /// Runs a decision with the user's input.
+    pub async fn input(&self, id: &str, input: String) -> Result<DecisionResult, ApiError> {
+        let conversation = self.session(id)?;
+        let mut conversation = conversation.lock().await;
+        let Conversation { state, session, .. } = &mut *conversation;
+        run_decision(Some(input), state, session)
+            .await?
+            .ok_or_else(|| ApiError::Internal("No decision result".into()))
+    }
+
+    pub async fn get(&self, id: &str) -> Result<SessionInfo, ApiError> {
+        let conversation = self.session(id)?;
+        let conversation = conversation.lock().await;
+        conversation.info(id)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), ApiError> {
+        lock(&self.inner.sessions)
+            .remove(id)
+            .map(|_| ())
+            .ok_or_else(|| not_found(id))
+    }
+
+    fn session(&self, id: &str) -> Result<Arc<Mutex<Conversation>>, ApiError> {
+        lock(&self.inner.sessions)
+            .get(id)
+            .cloned()
+            .ok_or_else(|| not_found(id))
+    }
+
+    // Decision state for the named tree, loaded on first use
+    fn state(&self, tree: &str) -> Result<Arc<DecisionState>, ApiError> {
+        if let Some(state) = lock(&self.inner.states).get(tree) {
+            return Ok(state.clone());
+        }
+        let state = Arc::new(self.load(tree)?);
+        Ok(lock(&self.inner.states)
+            .entry(tree.to_owned())
+            .or_insert(state)
+            .clone())
+    }
+
+    fn load(&self, tree: &str) -> Result<DecisionState, ApiError> {
+        let settings = &self.inner.settings;
+        let valid_name = !tree.is_empty()
+            && tree
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid tree name '{}'",
+                tree
+            )));
+        }
+
+        let path = settings.trees.join(format!("{}.yaml", tree));
+        if !path.is_file() {
+            return Err(ApiError::NotFound(format!("Tree '{}' not found", tree)));
+        }
+        let decision_tree = CompiledTree::load(&path).map_err(|err| {
+            ApiError::Internal(format!("Unable to load tree '{}': {}", tree, err.0))
+        })?;
+
+        let decision_prompt_template =
+            std::fs::read_to_string(&settings.template).map_err(|err| {
+                ApiError::Internal(format!(
+                    "Unable to read {}: {}",
+                    settings.template.display(),
+                    err
+                ))
+            })?;
+        let decision_prompt_template = DecisionPromptTemplate::new(decision_prompt_template);
+
+        let mut state =
+            DecisionState::new(&settings.config, decision_prompt_template, decision_tree)?;
+        for tool in tools::load_tools(&settings.config)? {
+            state.add_tool(tool);
+        }
+        Ok(state)
+    }
+}
+
+fn lock<T>(mutex: &std::sync::Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("Session '{}' not found", id))
+}
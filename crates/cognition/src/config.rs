@@ -1,33 +1,471 @@
-use serde_json::Value;
-use serde_yaml;
-
-pub fn object_by_path(config: &String, search_path: &str) -> Option<Value> {
-    let yaml_value: Value =
-        serde_yaml::from_str(&config).expect("Unable to parse the YAML content.");
-    let mut current_value = &yaml_value;
-    let path_parts: Vec<&str> = search_path.split(".").collect();
-
-    for part in path_parts {
-        if let Some(map) = current_value.as_object() {
-            if let Some(next_value) = map.get(part) {
-                current_value = next_value;
-            } else {
-                return None;
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+/// Configuration for models, engine limits and file paths.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CognitionConfig {
+    pub models: ModelsConfig,
+    pub engine: EngineConfig,
+    pub paths: PathsConfig,
+}
+
+/// The `models` section: the default backend and one section per backend.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelsConfig {
+    /// Backend created by `DecisionState::new`.
+    pub default: Option<String>,
+    #[serde(flatten)]
+    pub backends: Sections,
+}
+
+impl ModelsConfig {
+    /// Deserializes the section of the named backend.
+    pub fn backend<T: DeserializeOwned>(&self, name: &str) -> Result<T, ConfigError> {
+        self.backends.get("models", name)
+    }
+
+    /// Deserializes the section of the named backend, or returns the default if it is absent.
+    pub fn backend_or_default<T: DeserializeOwned + Default>(
+        &self,
+        name: &str,
+    ) -> Result<T, ConfigError> {
+        self.backends.get_or_default("models", name)
+    }
+}
+
+/// Named sub-sections that are deserialized on demand by whoever owns them, such as
+/// `models.davinci003`.
+///
+/// Sections are kept uninterpolated until they are deserialized, so a missing variable only
+/// fails the backend that uses it. `Debug` output lists the section names only.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct Sections(HashMap<String, Value>);
+
+impl fmt::Debug for Sections {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        f.debug_tuple("Sections").field(&names).finish()
+    }
+}
+
+/// A configuration value, such as an API key, that is never shown in `Debug` or `Display`
+/// output and is serialized redacted.
+#[derive(Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// The secret value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Limits and defaults for `run_decision`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// Maximum number of choices predicted from one user input.
+    pub max_prediction_depth: usize,
+    /// Maximum number of tokens generated for a decision.
+    pub max_tokens: usize,
+    /// Sampling temperature for decisions.
+    pub temperature: f32,
+    /// Sequences that end the model's answer to a decision prompt.
+    pub stop: Vec<String>,
+    /// Similarity threshold for fuzzy choice matching.
+    pub match_threshold: f32,
+    /// Maximum number of times the model is asked for valid tool arguments.
+    pub max_argument_attempts: usize,
+    /// Line appended to the history after a tool runs, with `{{id}}`, `{{name}}` and
+    /// `{{response}}` placeholders. Tool responses are not added to the history if unset.
+    pub tool_history: Option<String>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            max_prediction_depth: 5,
+            max_tokens: 200,
+            temperature: 0.5,
+            stop: vec![],
+            match_threshold: crate::matching::DEFAULT_THRESHOLD,
+            max_argument_attempts: 3,
+            tool_history: Some("- {{name}}: {{response}}".into()),
+        }
+    }
+}
+
+/// Default locations of the decision tree and prompt template.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Decision tree used by `cognition-cli run`.
+    pub tree: PathBuf,
+    /// Decision prompt template.
+    pub template: PathBuf,
+    /// Directory of named trees served by `cognition-server`.
+    pub trees: PathBuf,
+    /// Tool definitions loaded by `cognition-cli` and `cognition-server`.
+    pub tools: Vec<PathBuf>,
+}
+
+impl PathsConfig {
+    // Makes relative paths relative to the given directory
+    fn resolve(&mut self, dir: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+        resolve(&mut self.tree);
+        resolve(&mut self.template);
+        resolve(&mut self.trees);
+        self.tools.iter_mut().for_each(resolve);
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            tree: "decision_tree.yaml".into(),
+            template: "decision_prompt_template.yaml".into(),
+            trees: ".".into(),
+            tools: vec!["wolfram_tool.yaml".into(), "signal_book.yaml".into()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io { path: PathBuf, message: String },
+    /// The configuration is not valid YAML.
+    Parse(String),
+    /// A required key is missing.
+    MissingKey(String),
+    /// A key has an invalid value.
+    Invalid { key: String, message: String },
+    /// A `${...}` reference in a value could not be resolved.
+    Unresolved { key: String, message: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, message } => {
+                write!(f, "Unable to read {}: {}", path.display(), message)
+            }
+            ConfigError::Parse(message) => write!(f, "Unable to parse config: {}", message),
+            ConfigError::MissingKey(key) => write!(f, "Missing config key '{}'", key),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "Invalid config key '{}': {}", key, message)
             }
+            ConfigError::Unresolved { key, message } => {
+                write!(f, "Unable to resolve config key '{}': {}", key, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for crate::CognitionError {
+    fn from(err: ConfigError) -> Self {
+        crate::CognitionError(err.to_string())
+    }
+}
+
+impl CognitionConfig {
+    /// Parses the configuration from YAML, resolving `${...}` references (see `interpolate`).
+    pub fn from_yaml(yaml: &str) -> Result<Self, ConfigError> {
+        let mut value: Value =
+            serde_yaml::from_str(yaml).map_err(|err| ConfigError::Parse(err.to_string()))?;
+        // Backend sections are resolved when they are deserialized
+        let is_section = |key: &str| {
+            let mut parts = key.split('.');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("models"), Some(name), None) => name != "default",
+                _ => false,
+            }
+        };
+        interpolate_value(&mut value, "", &is_section)?;
+        serde_yaml::from_value(value).map_err(|err| ConfigError::Parse(err.to_string()))
+    }
+
+    /// Reads and parses a YAML configuration file. Relative `paths` are relative to the
+    /// directory of the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let yaml = std::fs::read_to_string(path).map_err(|err| ConfigError::Io {
+            path: path.to_owned(),
+            message: err.to_string(),
+        })?;
+        let mut config = Self::from_yaml(&yaml)?;
+        if let Some(dir) = path.parent() {
+            config.paths.resolve(dir);
+        }
+        Ok(config)
+    }
+
+    /// Builds a configuration from the environment: `COGNITION_MODEL` selects the model,
+    /// `OPENAI_API_KEY` configures `davinci003` and `chat_completions`, `OPENAI_BASE_URL` and
+    /// `OPENAI_MODEL` configure `chat_completions`, and `TEXTGEN_SERVER` configures `textgen`.
+    pub fn from_env() -> Self {
+        let is_set = |name: &str| std::env::var_os(name).is_some();
+        let reference = |name: &str| format!("${{{}}}", name);
+        let mut config = Self::default();
+        config.models.default = std::env::var("COGNITION_MODEL").ok();
+        if is_set("OPENAI_API_KEY") {
+            for backend in ["davinci003", "chat_completions"] {
+                config
+                    .models
+                    .backends
+                    .insert(backend, "api_key", reference("OPENAI_API_KEY"));
+            }
+        }
+        if is_set("OPENAI_BASE_URL") {
+            config.models.backends.insert(
+                "chat_completions",
+                "base_url",
+                reference("OPENAI_BASE_URL"),
+            );
+        }
+        if is_set("OPENAI_MODEL") {
+            config
+                .models
+                .backends
+                .insert("chat_completions", "model", reference("OPENAI_MODEL"));
+        }
+        if is_set("TEXTGEN_SERVER") {
+            config
+                .models
+                .backends
+                .insert("textgen", "server", reference("TEXTGEN_SERVER"));
+        }
+        config
+    }
+}
+
+impl Sections {
+    /// Deserializes the named section, reporting errors under `<prefix>.<name>`.
+    pub fn get<T: DeserializeOwned>(&self, prefix: &str, name: &str) -> Result<T, ConfigError> {
+        let key = format!("{}.{}", prefix, name);
+        let mut value = self
+            .0
+            .get(name)
+            .ok_or_else(|| ConfigError::MissingKey(key.clone()))?
+            .clone();
+        interpolate_value(&mut value, &key, &|_| false)?;
+        deserialize(&key, value)
+    }
+
+    /// Deserializes the named section, or returns the default if it is absent.
+    pub fn get_or_default<T: DeserializeOwned + Default>(
+        &self,
+        prefix: &str,
+        name: &str,
+    ) -> Result<T, ConfigError> {
+        if self.contains(name) {
+            self.get(prefix, name)
         } else {
-            return None;
+            Ok(T::default())
         }
     }
 
-    Some(current_value.clone())
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Sets `key` in the named section, creating the section if needed.
+    pub fn insert(&mut self, name: &str, key: &str, value: impl Into<Value>) {
+        let section = self
+            .0
+            .entry(name.to_owned())
+            .or_insert_with(|| Value::Mapping(Default::default()));
+        if let Value::Mapping(mapping) = section {
+            mapping.insert(Value::String(key.to_owned()), value.into());
+        }
+    }
 }
 
-pub fn string_by_path(config: &String, search_path: &str) -> Option<String> {
-    let value = object_by_path(config, search_path);
-    if let Some(value) = value {
-        if let Some(value) = value.as_str() {
-            return Some(value.to_string());
+// Deserialize a section, naming the offending key in errors
+fn deserialize<T: DeserializeOwned>(key: &str, value: Value) -> Result<T, ConfigError> {
+    serde_yaml::from_value(value).map_err(|err| {
+        let message = err.to_string();
+        let missing = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next());
+        match missing {
+            Some(field) => ConfigError::MissingKey(format!("{}.{}", key, field)),
+            None => ConfigError::Invalid {
+                key: key.to_owned(),
+                message,
+            },
         }
+    })
+}
+
+// Resolve references in all strings below `value`, except in keys for which `skip` is true
+fn interpolate_value(
+    value: &mut Value,
+    key: &str,
+    skip: &dyn Fn(&str) -> bool,
+) -> Result<(), ConfigError> {
+    let child = |name: &str| {
+        if key.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", key, name)
+        }
+    };
+    match value {
+        Value::String(text) => {
+            *text = interpolate(text).map_err(|message| ConfigError::Unresolved {
+                key: key.to_owned(),
+                message,
+            })?;
+        }
+        Value::Sequence(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", key, i), skip)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (name, value) in mapping.iter_mut() {
+                let name = child(name.as_str().unwrap_or_default());
+                if !skip(&name) {
+                    interpolate_value(value, &name, skip)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Resolves references in a string: `${VAR}` is replaced by the environment variable `VAR`,
+/// `${VAR:-default}` falls back to `default` when `VAR` is unset or empty, and
+/// `${file:/path}` is replaced by the contents of the file without the trailing newline.
+/// `$${` is an escaped `${`.
+pub fn interpolate(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        // Escaped reference
+        if start > 0 && rest[..start].ends_with('$') {
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated reference in '{}'", text))?;
+        result.push_str(&resolve(&rest[start + 2..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// Resolve the inside of a single `${...}` reference
+fn resolve(reference: &str) -> Result<String, String> {
+    if let Some(path) = reference.strip_prefix("file:") {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read file '{}': {}", path, err))?;
+        return Ok(content.trim_end_matches(['\n', '\r']).to_owned());
+    }
+    match reference.split_once(":-") {
+        Some((name, default)) => Ok(std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| default.to_owned())),
+        None => std::env::var(reference)
+            .map_err(|_| format!("Environment variable '{}' is not set", reference)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_environment_variables() {
+        std::env::set_var("COGNITION_TEST_SET", "value");
+        std::env::set_var("COGNITION_TEST_EMPTY", "");
+        assert_eq!(
+            interpolate("a ${COGNITION_TEST_SET} b").unwrap(),
+            "a value b"
+        );
+        assert_eq!(
+            interpolate("${COGNITION_TEST_SET:-default}").unwrap(),
+            "value"
+        );
+        assert_eq!(
+            interpolate("${COGNITION_TEST_EMPTY:-default}").unwrap(),
+            "default"
+        );
+        assert_eq!(interpolate("${COGNITION_TEST_UNSET:-}").unwrap(), "");
+        assert_eq!(
+            interpolate("${COGNITION_TEST_UNSET}").unwrap_err(),
+            "Environment variable 'COGNITION_TEST_UNSET' is not set"
+        );
+    }
+
+    #[test]
+    fn reads_files() {
+        let path = std::env::temp_dir().join("cognition_interpolate_test.txt");
+        std::fs::write(&path, "secret\n").unwrap();
+        let text = format!("key: ${{file:{}}}", path.display());
+        assert_eq!(interpolate(&text).unwrap(), "key: secret");
+        std::fs::remove_file(&path).unwrap();
+        assert!(interpolate(&text)
+            .unwrap_err()
+            .starts_with("Unable to read file"));
+    }
+
+    #[test]
+    fn leaves_escaped_and_plain_text_alone() {
+        assert_eq!(interpolate("no references").unwrap(), "no references");
+        assert_eq!(
+            interpolate("$${HOME} costs $5").unwrap(),
+            "${HOME} costs $5"
+        );
+        assert_eq!(
+            interpolate("${UNTERMINATED").unwrap_err(),
+            "Unterminated reference in '${UNTERMINATED'"
+        );
     }
-    None
 }
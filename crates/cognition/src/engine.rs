@@ -1,25 +1,205 @@
 use crate::{
-    models::{self, LargeLanguageModel},
-    CognitionError, DecisionPromptTemplate, Tool, ToolResponse,
+    config::{CognitionConfig, EngineConfig},
+    expression::{Expression, ExpressionError},
+    matching::{normalize, ChoiceMatch, ChoiceMatcher, FirstMatch},
+    models::{GenerationParams, LargeLanguageModel, ModelRegistry},
+    parameters::{parse_object, Parameter, ParameterType, Parameters},
+    session::Session,
+    tree::{self, CompiledTree, NodeIndex},
+    validation::{self, Diagnostic, START_ID},
+    ArgumentsPromptTemplate, CognitionError, DecisionPromptTemplate, Tool, ToolResponse,
 };
 use log::*;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Choice the decision prompt template offers when the user's intent is unclear.
+pub const AMBIGUOUS_CHOICE: &str = "Ambiguous";
+
+/// Choice text recorded when a node's `else` edge is taken.
+pub const ELSE_CHOICE: &str = "else";
 
 // YAML decision node structure
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Decision {
     pub id: String,
+    #[serde(default)]
     pub text: String,
     pub predicted_text: Option<String>,
     pub tool: Option<String>,
     pub predict: Option<bool>,
     pub reset: Option<bool>,
+    /// Follow-up for an ambiguous answer: the id of a node to move to, or a question
+    /// to ask before re-asking this node.
+    pub clarify: Option<String>,
+    /// Variables assigned when the node is reached. Strings may reference the session
+    /// context, e.g. `{{tools.wolfram_alpha.response}}`.
+    pub set: Option<BTreeMap<String, Value>>,
+    /// Values the model extracts from the user's answer to this node into the session
+    /// variables. The node is asked again while a required slot is missing.
+    pub slots: Option<BTreeMap<String, Slot>>,
     pub choices: Option<Vec<Choice>>,
+    /// Node to move to when the user's answer matches none of the available choices.
+    #[serde(rename = "else")]
+    pub else_id: Option<String>,
+    /// Sub-tree the conversation continues in when the node is reached.
+    pub call: Option<Call>,
+    /// Overrides `engine.max_tokens` when predicting the choice at this node.
+    pub max_tokens: Option<usize>,
+    /// Overrides `engine.temperature` when predicting the choice at this node.
+    pub temperature: Option<f32>,
+    /// Overrides `engine.max_prediction_depth` for answers given at this node.
+    pub max_prediction_depth: Option<usize>,
+    /// Overrides `engine.stop` when predicting the choice at this node.
+    pub stop: Option<Vec<String>>,
 }
 
 impl Decision {
-    pub fn choices(&self) -> Vec<&Choice> {
-        self.choices.iter().flat_map(|choice| choice).collect()
+    /// Choices whose `when` guard holds in the given session context.
+    pub fn choices(&self, context: &Value) -> Vec<&Choice> {
+        self.choices
+            .iter()
+            .flatten()
+            .filter(|choice| choice.is_available(context))
+            .collect()
+    }
+
+    /// All choices, regardless of their guards.
+    pub fn all_choices(&self) -> Vec<&Choice> {
+        self.choices.iter().flatten().collect()
+    }
+
+    /// Ids of the nodes this node's choices, `else` and `call.return_to` lead to.
+    pub fn next_ids(&self) -> Vec<&str> {
+        self.all_choices()
+            .iter()
+            .map(|choice| choice.next_id.as_str())
+            .chain(self.else_id.as_deref())
+            .chain(self.call.as_ref().map(|call| call.return_to.as_str()))
+            .collect()
+    }
+
+    /// Whether the node ends the conversation, or the sub-tree it is in: it has neither
+    /// choices, an `else` nor a `call`.
+    pub fn is_terminal(&self) -> bool {
+        self.all_choices().is_empty() && self.else_id.is_none() && self.call.is_none()
+    }
+
+    /// Parameters for predicting the choice at this node: the engine defaults, with the
+    /// node's overrides applied.
+    pub fn generation_params(&self, engine: &EngineConfig) -> GenerationParams {
+        GenerationParams {
+            max_tokens: self.max_tokens.unwrap_or(engine.max_tokens),
+            temperature: self.temperature.unwrap_or(engine.temperature),
+            stop: self.stop.clone().unwrap_or_else(|| engine.stop.clone()),
+        }
+    }
+
+    // The `else` edge as a choice
+    fn else_choice(&self) -> Option<Choice> {
+        self.else_id.as_ref().map(|else_id| Choice {
+            text: ELSE_CHOICE.to_owned(),
+            next_id: else_id.clone(),
+            when: None,
+            guard: None,
+        })
+    }
+}
+
+/// A call into a sub-tree. The conversation continues at the sub-tree's `start` node, and
+/// returns to `return_to` with the answer to the node without choices that ends it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Call {
+    /// YAML file of the sub-tree, relative to the file of the calling tree.
+    pub tree: PathBuf,
+    /// Node to continue at when the sub-tree ends.
+    pub return_to: String,
+    /// Prefix of the sub-tree's node ids; defaults to the file name without extension.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl Call {
+    /// Prefix of the sub-tree's node ids, e.g. `feedback` in `feedback::start`.
+    pub fn namespace(&self) -> String {
+        self.namespace.clone().unwrap_or_else(|| {
+            self.tree
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Id of the sub-tree's `start` node.
+    pub fn start_id(&self) -> String {
+        tree::namespaced(&self.namespace(), START_ID)
+    }
+}
+
+/// A value extracted from the user's answer to a node, written either as its type
+/// (`quantity: integer`) or in full (`quantity: {type: integer, prompt: "How many?"}`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "SlotSpec")]
+pub struct Slot {
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+    pub description: Option<String>,
+    /// Question asked when the slot is missing.
+    pub prompt: Option<String>,
+    pub required: bool,
+}
+
+impl Slot {
+    /// Question asked when the named slot is missing.
+    pub fn prompt(&self, name: &str) -> String {
+        self.prompt
+            .clone()
+            .unwrap_or_else(|| format!("What is the {}?", name.replace('_', " ")))
+    }
+}
+
+// Shorthand and full forms of a slot
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SlotSpec {
+    Type(ParameterType),
+    Full {
+        #[serde(rename = "type")]
+        kind: ParameterType,
+        description: Option<String>,
+        prompt: Option<String>,
+        #[serde(default = "required_by_default")]
+        required: bool,
+    },
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+impl From<SlotSpec> for Slot {
+    fn from(spec: SlotSpec) -> Self {
+        match spec {
+            SlotSpec::Type(kind) => Self {
+                kind,
+                description: None,
+                prompt: None,
+                required: true,
+            },
+            SlotSpec::Full {
+                kind,
+                description,
+                prompt,
+                required,
+            } => Self {
+                kind,
+                description,
+                prompt,
+                required,
+            },
+        }
     }
 }
 
@@ -28,47 +208,114 @@ impl Decision {
 pub struct Choice {
     #[serde(rename = "choice")]
     pub text: String,
-    next_id: String,
+    pub(crate) next_id: String,
+    /// Guard expression; the choice is only offered while it holds (see `Expression`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    // `when`, parsed when the tree is compiled
+    #[serde(skip)]
+    guard: Option<Expression>,
+}
+
+impl Choice {
+    /// Id of the node this choice leads to.
+    pub fn next_id(&self) -> &str {
+        &self.next_id
+    }
+
+    /// Whether the guard holds in the given session context. Guards of a `CompiledTree` are
+    /// parsed once; other guards are parsed on each call, and never hold if they do not parse.
+    pub fn is_available(&self, context: &Value) -> bool {
+        if let Some(guard) = &self.guard {
+            return guard.is_true(context);
+        }
+        match self.when.as_deref().map(Expression::parse) {
+            None => true,
+            Some(Ok(guard)) => guard.is_true(context),
+            Some(Err(err)) => {
+                warn!(
+                    "Invalid guard '{}': {}",
+                    self.when.as_deref().unwrap_or_default(),
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    pub(crate) fn compile_guard(&mut self) -> Result<(), ExpressionError> {
+        self.guard = self.when.as_deref().map(Expression::parse).transpose()?;
+        Ok(())
+    }
 }
 
+/// Shared, immutable part of the engine: the decision tree, prompt template, model and
+/// tools. Per-conversation state lives in a `Session`.
+///
+/// `DecisionState` is `Send + Sync`, so it can be shared behind an `Arc` and
+/// `run_decision` can be spawned on a multi-threaded runtime.
 pub struct DecisionState {
     model: Box<dyn LargeLanguageModel>,
-    decision_nodes: Vec<Decision>,
+    tree: CompiledTree,
     decision_prompt_template: DecisionPromptTemplate,
+    arguments_prompt_template: ArgumentsPromptTemplate,
     tools: Vec<Box<dyn Tool>>,
+    matcher: Box<dyn ChoiceMatcher>,
+    engine: EngineConfig,
     pub agent: String,
     pub user: String,
-    history: String,
-    current_id: String,
 }
 
 impl DecisionState {
+    /// Creates a decision state using the model selected by `models.default` in the config.
     pub fn new(
-        config: &String,
+        config: &CognitionConfig,
         decision_prompt_template: DecisionPromptTemplate,
-        decision_nodes: Vec<Decision>,
-    ) -> Self {
-        // LLM model
-        let model = models::davinci003::Davinci003::new(config).unwrap();
-        // let model = models::textgen::Textgen::new("").unwrap();
+        tree: CompiledTree,
+    ) -> Result<Self, CognitionError> {
+        Self::with_registry(
+            &ModelRegistry::default(),
+            config,
+            decision_prompt_template,
+            tree,
+        )
+    }
+
+    /// Creates a decision state using a model constructed from the given registry, and the
+    /// engine limits from the `engine` section of the config.
+    pub fn with_registry(
+        registry: &ModelRegistry,
+        config: &CognitionConfig,
+        decision_prompt_template: DecisionPromptTemplate,
+        tree: CompiledTree,
+    ) -> Result<Self, CognitionError> {
+        let model = registry
+            .from_config(config)
+            .map_err(|err| CognitionError(format!("Failed to create model: {}", err)))?;
+        let mut state = Self::with_model(model, decision_prompt_template, tree);
+        state.set_engine_config(config.engine.clone());
+        Ok(state)
+    }
 
+    /// Creates a decision state around an already constructed model, with default engine limits.
+    pub fn with_model(
+        model: Box<dyn LargeLanguageModel>,
+        decision_prompt_template: DecisionPromptTemplate,
+        tree: CompiledTree,
+    ) -> Self {
         let agent = "Agent".into();
         let user = "User".into();
 
-        let history = String::new();
-
-        // Initialize the decision loop
-        let current_id = "start".to_string();
-
         Self {
-            model: Box::new(model),
-            decision_nodes,
+            model,
+            tree,
             decision_prompt_template,
+            arguments_prompt_template: ArgumentsPromptTemplate::default(),
             tools: vec![],
+            matcher: Box::new(FirstMatch::default()),
+            engine: EngineConfig::default(),
             agent,
             user,
-            history,
-            current_id,
         }
     }
 
@@ -77,92 +324,319 @@ impl DecisionState {
         self.tools.push(tool);
     }
 
-    fn decision_node(&self, id: &str) -> Result<&Decision, CognitionError> {
-        self.decision_nodes
-            .iter()
-            .find(|node| node.id == id)
+    /// Sets the engine limits, and resets the choice matcher to the standard strategies
+    /// with the configured similarity threshold.
+    pub fn set_engine_config(&mut self, engine: EngineConfig) {
+        self.matcher = Box::new(FirstMatch::standard(engine.match_threshold));
+        self.engine = engine;
+    }
+
+    /// Replaces the prompt used to fill in tool arguments.
+    pub fn set_arguments_prompt_template(&mut self, template: ArgumentsPromptTemplate) {
+        self.arguments_prompt_template = template;
+    }
+
+    /// Replaces the strategy used to match model responses to choices.
+    pub fn set_matcher(&mut self, matcher: Box<dyn ChoiceMatcher>) {
+        self.matcher = matcher;
+    }
+
+    /// The decision tree.
+    pub fn tree(&self) -> &CompiledTree {
+        &self.tree
+    }
+
+    fn node_index(&self, id: &str) -> Result<NodeIndex, CognitionError> {
+        self.tree
+            .index_of(id)
             .ok_or_else(|| CognitionError(format!("Decision node with ID '{}' not found", id)))
     }
 
-    pub fn current_node(&self) -> Result<&Decision, CognitionError> {
-        self.decision_node(&self.current_id)
+    /// The decision node the session is at.
+    pub fn current_node(&self, session: &Session) -> Result<&Decision, CognitionError> {
+        Ok(self.tree.node(self.node_index(&session.current_id)?))
+    }
+
+    /// Checks that a session, e.g. one restored from a client, fits this tree: its current node
+    /// must exist, and its call stack may only hold nodes that a `call` returns to.
+    pub fn check_session(&self, session: &Session) -> Result<(), CognitionError> {
+        self.current_node(session)?;
+        for id in &session.call_stack {
+            let returned_to = self
+                .tree
+                .index_of(id)
+                .is_some_and(|index| self.tree.is_return_target(index));
+            if !returned_to {
+                return Err(CognitionError(format!("No call returns to node '{}'", id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the decision tree against the registered tools.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let tool_ids: Vec<&str> = self.tools.iter().map(|tool| tool.id().as_str()).collect();
+        validation::validate_nodes(self.tree.nodes(), &tool_ids)
+    }
+}
+
+/// Why `run_decision` did or did not advance.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChoiceOutcome {
+    /// No user input was given, so no choice was made.
+    NoInput,
+    /// The current node has no choices.
+    Terminal,
+    /// The model's response matched one of the choices.
+    Matched,
+    /// The node has a single choice, which was taken without asking the model.
+    SingleChoice,
+    /// The model could not tell which choice the user meant.
+    Ambiguous,
+    /// The model's response did not match any choice.
+    NoMatch,
+    /// Prediction stopped at a node with `predict: false`.
+    PredictionDisabled,
+    /// Prediction stopped at the maximum prediction depth.
+    DepthLimit,
+    /// Required slots of the current node are missing from the user's answer.
+    MissingSlots,
+    /// Prediction stopped at a node whose slots are filled from the answer to its question.
+    SlotsRequired,
+    /// No available choice matched, so the node's `else` edge was taken.
+    Fallback,
+    /// The user answered the end of a sub-tree, so the conversation returned to the caller.
+    Returned,
+}
+
+impl ChoiceOutcome {
+    // Records an outcome: the outcome of the user's own answer, or why prediction stopped
+    fn record(
+        self,
+        predicting: bool,
+        outcome: &mut ChoiceOutcome,
+        prediction_stop: &mut Option<ChoiceOutcome>,
+    ) {
+        if predicting {
+            *prediction_stop = Some(self);
+        } else {
+            *outcome = self;
+        }
     }
 }
 
-#[derive(Debug)]
+impl std::fmt::Display for ChoiceOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            ChoiceOutcome::NoInput => "no input",
+            ChoiceOutcome::Terminal => "no choices available",
+            ChoiceOutcome::Matched => "matched a choice",
+            ChoiceOutcome::SingleChoice => "advanced through the only choice",
+            ChoiceOutcome::Ambiguous => "answer was ambiguous",
+            ChoiceOutcome::NoMatch => "answer did not match any choice",
+            ChoiceOutcome::PredictionDisabled => "prediction disabled at this node",
+            ChoiceOutcome::DepthLimit => "reached the prediction depth limit",
+            ChoiceOutcome::MissingSlots => "required values are missing",
+            ChoiceOutcome::SlotsRequired => "the next question asks for values",
+            ChoiceOutcome::Fallback => "took the else edge",
+            ChoiceOutcome::Returned => "returned from the sub-tree",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Serialize, Debug)]
 pub struct DecisionResult {
     pub user_input: Option<String>,
     pub decision_prompt: Option<String>,
     pub choice: Option<String>,
+    pub choice_match: Option<ChoiceMatch>,
+    /// What came of the user's answer to the node the session was at.
+    pub outcome: ChoiceOutcome,
+    /// Why prediction stopped, if a choice was predicted from the user's answer.
+    pub prediction_stop: Option<ChoiceOutcome>,
+    /// Follow-up question to ask when the answer was ambiguous or a slot is missing.
+    pub clarification: Option<String>,
+    /// Required slots of the current node that are still missing.
+    pub missing_slots: Vec<String>,
     pub current_id: String,
     pub decision_node: Decision,
+    /// Text of the current node, with tool responses filled in.
+    pub text: String,
+    /// Choices of the current node whose guard holds.
+    pub choices: Vec<String>,
     pub predictions: Vec<Prediction>,
     pub tool_response: Option<ToolResponse>,
 }
 
-#[derive(Debug)]
+impl DecisionResult {
+    /// The user's answer to the current question was ambiguous.
+    pub fn is_ambiguous(&self) -> bool {
+        self.outcome == ChoiceOutcome::Ambiguous
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Prediction {
     pub choice: String,
     pub id: String,
+    pub choice_match: Option<ChoiceMatch>,
     pub tool_response: Option<ToolResponse>,
 }
 
 // Run the decision-making process using the decision tree
 pub async fn run_decision(
     user_input: Option<String>,
-    state: &mut DecisionState,
+    state: &DecisionState,
+    session: &mut Session,
 ) -> Result<Option<DecisionResult>, CognitionError> {
     let mut predicting_choice = false;
     let mut tool_response = None;
     let mut decision_prompt = None;
-    let choice: Option<String> = None;
+    let mut choice = None;
+    let mut choice_match = None;
+    let mut outcome = ChoiceOutcome::NoInput;
+    let mut prediction_stop = None;
+    let mut clarification = None;
+    let mut missing_slots = vec![];
     let mut predictions = vec![];
-    let mut max_depth = 5;
+    // The current node is looked up once; from there the engine follows resolved edges
+    let mut current = state.node_index(&session.current_id)?;
+    let mut max_depth = state
+        .tree
+        .node(current)
+        .max_prediction_depth
+        .unwrap_or(state.engine.max_prediction_depth);
 
     loop {
-        let decision_node = state.decision_node(&state.current_id)?.clone();
+        let decision_node = state.tree.node(current);
+        let edges = state.tree.edges(current);
 
-        // Map choices to choices.choice
-        let choices: Vec<&Choice> = decision_node.choices();
+        // A session left at a call node, such as a new one whose `start` calls a sub-tree,
+        // visits it like any other node before continuing in the sub-tree
+        if decision_node.call.is_some() {
+            visit(
+                state,
+                session,
+                &mut current,
+                user_input.as_deref(),
+                &mut predicting_choice,
+                &mut prediction_stop,
+                &mut tool_response,
+            )
+            .await?;
+            continue;
+        }
 
         // If there are no choices, we're done
-        if choices.len() == 0 {
+        if decision_node.is_terminal() {
+            // The answer to the end of a sub-tree returns to the calling tree
+            if let (Some(user_input), false) = (&user_input, predicting_choice) {
+                if let Some(return_to) = session.call_stack.pop() {
+                    debug!("{} returns to {}", decision_node.id, return_to);
+                    push_exchange(state, session, decision_node, user_input);
+                    let return_to = state.node_index(&return_to)?;
+                    move_to(state, session, &mut current, return_to);
+                    outcome = ChoiceOutcome::Returned;
+                    visit(
+                        state,
+                        session,
+                        &mut current,
+                        Some(user_input),
+                        &mut predicting_choice,
+                        &mut prediction_stop,
+                        &mut tool_response,
+                    )
+                    .await?;
+                    break;
+                }
+            }
+            ChoiceOutcome::Terminal.record(predicting_choice, &mut outcome, &mut prediction_stop);
+            break;
+        }
+
+        // Slots are filled from the answer to the node's own question, so prediction stops
+        // at a node that has some
+        let has_slots = decision_node
+            .slots
+            .as_ref()
+            .is_some_and(|slots| !slots.is_empty());
+        if has_slots && predicting_choice {
+            prediction_stop = Some(ChoiceOutcome::SlotsRequired);
+            break;
+        }
+
+        // Fill the node's slots, and ask again for the ones that are missing
+        if let Some(user_input) = user_input.as_ref().filter(|_| has_slots) {
+            missing_slots = fill_slots(state, session, decision_node, user_input).await?;
+            if let Some(name) = missing_slots.first() {
+                info!("Missing slots at {}: {:?}", decision_node.id, missing_slots);
+                push_exchange(state, session, decision_node, user_input);
+                let slot = decision_node
+                    .slots
+                    .as_ref()
+                    .and_then(|slots| slots.get(name));
+                clarification = slot.map(|slot| slot.prompt(name));
+                outcome = ChoiceOutcome::MissingSlots;
+                break;
+            }
+        }
+
+        // Pair the choices with the nodes they lead to, hiding the ones whose guard does not
+        // hold
+        let context = session.context();
+        let choices: Vec<(&Choice, NodeIndex)> = decision_node
+            .all_choices()
+            .into_iter()
+            .zip(edges.choices.iter().copied())
+            .filter(|(choice, _)| choice.is_available(&context))
+            .collect();
+        let else_choice = decision_node.else_choice().zip(edges.else_node);
+        if choices.is_empty() && else_choice.is_none() {
+            ChoiceOutcome::Terminal.record(predicting_choice, &mut outcome, &mut prediction_stop);
             break;
         }
 
         // Select next choice
+        let mut step_match = None;
+        let mut step_ambiguous = false;
         let next_choice = if user_input.is_none() {
             // If user has not provided input, do not make a choice
             None
-        } else if choices.len() == 1 {
-            // If there is only one choice, select it
+        } else if choices.is_empty() {
+            // If every choice is hidden, fall back to the else edge below
+            None
+        } else if choices.len() == 1 && else_choice.is_none() {
+            // If there is only one choice and nowhere else to go, select it
             debug!("Only one choice, skip prediction");
             choices.first()
         } else if let Some(user_input) = &user_input {
-            // If many choices, predict best choice
+            // If many choices, or one besides the else edge, predict best choice
             info!("User input: {:?}", user_input);
 
             // Map choices to choice string
             let choice_texts: Vec<String> = choices
                 .iter()
-                .map(|choice| choice.text.trim().to_string())
+                .map(|(choice, _)| choice.text.trim().to_string())
                 .collect();
 
             let choices_str = choice_texts.join("\n  - ");
 
             // Create the decision prompt
-            let prompt = decision_node.text.clone();
-            let mut prompt = state.decision_prompt_template.format(
-                &state.history,
+            let prompt = session.render(&decision_node.text);
+            let mut prompt = state.decision_prompt_template.render(session).format(
+                &session.history,
                 &prompt,
                 &choices_str,
-                &user_input,
+                user_input,
             );
 
             // Few shot prediction
+            let params = decision_node.generation_params(&state.engine);
             let response = state
                 .model
-                .generate(&prompt, 200, 0.5)
+                .generate_with(&prompt, &params)
                 .await
                 .map_err(|err| CognitionError(format!("Failed to generate choice: {}", err)))?;
             let response = response.text;
@@ -172,69 +646,169 @@ pub async fn run_decision(
             // Set current prompt
             decision_prompt = Some(prompt);
 
-            // Try to match the user's response with one of the choices
-            choice_texts
-                .iter()
-                .position(|choice| *choice == response)
-                .and_then(|index| choices.get(index))
+            // Try to match the model's response with one of the choices
+            step_ambiguous = normalize(&response).eq_ignore_ascii_case(AMBIGUOUS_CHOICE);
+            if !step_ambiguous {
+                step_match = state.matcher.find(&response, &choice_texts);
+            }
+            if let Some(step_match) = &step_match {
+                debug!(
+                    "Matched choice {} with {} ({:.2})",
+                    step_match.index, step_match.strategy, step_match.score
+                );
+            }
+            step_match
+                .as_ref()
+                .and_then(|step_match| choices.get(step_match.index))
         } else {
             None
         };
 
+        // If no choice was made from the user's answer, take the else edge
+        let step_fallback = next_choice.is_none() && user_input.is_some() && !step_ambiguous;
+        let next_choice = match next_choice {
+            Some(next) => Some(*next),
+            None if step_fallback => else_choice.as_ref().map(|(choice, index)| (choice, *index)),
+            None => None,
+        };
+
         // Update the history with the agent-user interaction
         if let Some(user_input) = &user_input {
             if !predicting_choice {
-                // Update the history with the current text
-                if state.history.len() > 0 {
-                    state.history.push_str(&format!("\n  "));
-                }
-                state
-                    .history
-                    .push_str(&format!("- {}: {}", state.agent, decision_node.text));
-                // Update the history with the user's response
-                state
-                    .history
-                    .push_str(&format!("\n  - {}: {}", state.user, user_input));
+                push_exchange(state, session, decision_node, user_input);
             }
         }
 
         // If there is a choice, get the next decision node ID
-        if let Some(choice) = next_choice {
+        if let Some((next, next_index)) = next_choice {
             info!(
                 "Predicting the user's next choice... {} {}",
                 decision_node.id, decision_node.text
             );
+            if !predicting_choice {
+                choice = Some(next.text.clone());
+                choice_match = step_match.clone();
+                outcome = if step_match.is_some() {
+                    ChoiceOutcome::Matched
+                } else if step_fallback {
+                    ChoiceOutcome::Fallback
+                } else {
+                    ChoiceOutcome::SingleChoice
+                };
+            }
             predictions.push(Prediction {
-                choice: choice.text.clone(),
-                id: choice.next_id.clone(),
+                choice: next.text.clone(),
+                id: next.next_id.clone(),
+                choice_match: step_match,
                 tool_response: tool_response.clone(),
             });
 
             predicting_choice = true;
             // Continue to the next decision node
-            state.current_id = choice.next_id.clone();
+            move_to(state, session, &mut current, next_index);
         }
 
-        // Find the current decision node
-        let decision_node = state.decision_node(&state.current_id)?.clone();
+        // If the user's answer was ambiguous, ask for clarification
+        if step_ambiguous && !predicting_choice {
+            info!("Ambiguous answer at {}", decision_node.id);
+            outcome = ChoiceOutcome::Ambiguous;
+            match edges.clarify {
+                Some(clarify) => move_to(state, session, &mut current, clarify),
+                None => clarification = decision_node.clarify.clone(),
+            }
+        }
+
+        // If there is no choice, disable prediction
+        if next_choice.is_none() {
+            if step_ambiguous && predicting_choice {
+                prediction_stop = Some(ChoiceOutcome::Ambiguous);
+            } else if user_input.is_some() && !step_ambiguous {
+                ChoiceOutcome::NoMatch.record(
+                    predicting_choice,
+                    &mut outcome,
+                    &mut prediction_stop,
+                );
+            }
+            predicting_choice = false;
+        }
+
+        visit(
+            state,
+            session,
+            &mut current,
+            user_input.as_deref(),
+            &mut predicting_choice,
+            &mut prediction_stop,
+            &mut tool_response,
+        )
+        .await?;
+
+        max_depth = max_depth.saturating_sub(1);
+        if !predicting_choice {
+            break;
+        }
+        if max_depth == 0 {
+            prediction_stop = Some(ChoiceOutcome::DepthLimit);
+            break;
+        }
+    }
+
+    session.predictions = predictions.clone();
+
+    let decision_node = state.tree.node(current).clone();
+    let result = DecisionResult {
+        user_input,
+        decision_prompt,
+        choice,
+        choice_match,
+        outcome,
+        prediction_stop,
+        clarification,
+        missing_slots,
+        current_id: session.current_id.clone(),
+        text: session.render(&decision_node.text),
+        choices: decision_node
+            .choices(&session.context())
+            .iter()
+            .map(|choice| choice.text.clone())
+            .collect(),
+        decision_node,
+        predictions,
+        tool_response,
+    };
+
+    Ok(Some(result))
+}
+
+// Visit the current decision node: reset the history, run its tool and assign its variables,
+// entering the sub-tree it calls
+async fn visit(
+    state: &DecisionState,
+    session: &mut Session,
+    current: &mut NodeIndex,
+    user_input: Option<&str>,
+    predicting_choice: &mut bool,
+    prediction_stop: &mut Option<ChoiceOutcome>,
+    tool_response: &mut Option<ToolResponse>,
+) -> Result<(), CognitionError> {
+    loop {
+        let decision_node = state.tree.node(*current);
 
         // If node has reset, reset the history
         if let Some(true) = decision_node.reset {
-            state.history = String::new();
+            session.history = String::new();
         }
 
         // If node doesn't support prediction, disable prediction
         if let Some(false) = decision_node.predict {
-            predicting_choice = false;
-        }
-
-        // If there is no choice, disable prediction
-        if next_choice.is_none() {
-            predicting_choice = false;
+            if *predicting_choice && !decision_node.is_terminal() {
+                *prediction_stop = Some(ChoiceOutcome::PredictionDisabled);
+            }
+            *predicting_choice = false;
         }
 
         // If there is a tool, run the tool and get the response
-        if let Some(user_input) = &user_input {
+        if let Some(user_input) = user_input {
             // If node has a tool, run the tool
             if let Some(tool_id) = &decision_node.tool {
                 // Find the tool
@@ -243,25 +817,534 @@ pub async fn run_decision(
                     .iter()
                     .find(|obj| *obj.id() == *tool_id)
                     .ok_or_else(|| CognitionError(format!("Could not find tool: {}", tool_id)))?;
-                tool_response = tool.run(user_input).await?;
+                let args = tool_arguments(state, tool.as_ref(), session, user_input).await?;
+                debug!("{} arguments: {}", tool_id, args);
+                *tool_response = tool.run(&args).await?;
+
+                // Keep the response for later text and prompts
+                if let Some(response) = tool_response {
+                    if let Some(format) = &state.engine.tool_history {
+                        let line = format
+                            .replace("{{id}}", &response.id)
+                            .replace("{{name}}", tool.name())
+                            .replace("{{response}}", &response.response);
+                        push_history(session, &line);
+                    }
+                    session
+                        .tool_responses
+                        .insert(response.id.clone(), response.clone());
+                }
             }
         }
 
-        max_depth -= 1;
-        if !predicting_choice || max_depth == 0 {
-            break;
+        // Assign the node's variables
+        for (name, value) in decision_node.set.iter().flatten() {
+            let value = match value {
+                Value::String(text) => Value::String(session.render(text)),
+                value => value.clone(),
+            };
+            session.variables.insert(name.clone(), value);
+        }
+
+        match (&decision_node.call, state.tree.edges(*current).call_start) {
+            (Some(call), Some(start)) => {
+                debug!("{} calls {}", decision_node.id, call.start_id());
+                session.call_stack.push(call.return_to.clone());
+                move_to(state, session, current, start);
+            }
+            _ => return Ok(()),
         }
     }
+}
 
-    let result = DecisionResult {
-        user_input,
-        decision_prompt,
-        choice,
-        current_id: state.current_id.clone(),
-        decision_node: state.current_node()?.clone(),
-        predictions,
-        tool_response,
+// Move the session to a node
+fn move_to(state: &DecisionState, session: &mut Session, current: &mut NodeIndex, next: NodeIndex) {
+    *current = next;
+    session.current_id.clone_from(&state.tree.node(next).id);
+}
+
+// Append a line to the history
+fn push_history(session: &mut Session, line: &str) {
+    if !session.history.is_empty() {
+        session.history.push_str("\n  ");
+    }
+    session.history.push_str(line);
+}
+
+// Append the current text and the user's response to the history
+fn push_exchange(state: &DecisionState, session: &mut Session, node: &Decision, user_input: &str) {
+    let text = session.render(&node.text);
+    push_history(session, &format!("- {}: {}", state.agent, text));
+    push_history(session, &format!("- {}: {}", state.user, user_input));
+}
+
+// Extract the node's slots from the user input into the session variables, returning the
+// required slots that are still missing
+async fn fill_slots(
+    state: &DecisionState,
+    session: &mut Session,
+    node: &Decision,
+    user_input: &str,
+) -> Result<Vec<String>, CognitionError> {
+    let slots = match &node.slots {
+        Some(slots) if !slots.is_empty() => slots,
+        _ => return Ok(vec![]),
     };
 
-    Ok(Some(result))
+    // Missing values are re-asked rather than re-prompted, so none is required here
+    let parameters = Parameters {
+        properties: slots
+            .iter()
+            .map(|(name, slot)| {
+                let parameter = Parameter {
+                    kind: slot.kind,
+                    description: slot.description.clone(),
+                    allowed: None,
+                };
+                (name.clone(), parameter)
+            })
+            .collect(),
+        required: vec![],
+    };
+    let text = session.render(&node.text);
+    // An answer the model cannot extract values from leaves all of them missing
+    let values = fill_arguments(state, session, &node.id, &text, &parameters, user_input)
+        .await?
+        .unwrap_or_default();
+    for (name, value) in values.as_object().into_iter().flatten() {
+        if slots.contains_key(name) && !value.is_null() {
+            session.variables.insert(name.clone(), value.clone());
+        }
+    }
+
+    Ok(slots
+        .iter()
+        .filter(|(name, slot)| slot.required && !session.variables.contains_key(*name))
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+// Fill in the arguments of a tool, or its input if it takes no parameters
+async fn tool_arguments(
+    state: &DecisionState,
+    tool: &dyn Tool,
+    session: &Session,
+    user_input: &str,
+) -> Result<Value, CognitionError> {
+    match tool.parameters() {
+        Some(parameters) => {
+            let (name, description) = (tool.name(), tool.description());
+            fill_arguments(state, session, name, description, parameters, user_input)
+                .await?
+                .map_err(|errors| {
+                    CognitionError(format!(
+                        "Unable to fill in the arguments of '{}': {}",
+                        name,
+                        errors.join("; ")
+                    ))
+                })
+        }
+        None => Ok(json!({ "input": user_input })),
+    }
+}
+
+// Fill in values described by parameters with the model, re-prompting until they are valid.
+// Returns the problems with the last response if none was valid.
+async fn fill_arguments(
+    state: &DecisionState,
+    session: &Session,
+    name: &str,
+    description: &str,
+    parameters: &Parameters,
+    user_input: &str,
+) -> Result<Result<Value, Vec<String>>, CognitionError> {
+    let schema = parameters.schema().to_string();
+    let mut errors = vec![];
+    for attempt in 1..=state.engine.max_argument_attempts {
+        let prompt = state.arguments_prompt_template.render(session).format(
+            &session.history,
+            name,
+            description,
+            &schema,
+            user_input,
+            &errors,
+        );
+        let response = state
+            .model
+            .generate(&prompt, state.engine.max_tokens, state.engine.temperature)
+            .await
+            .map_err(|err| CognitionError(format!("Failed to generate arguments: {}", err)))?;
+        debug!("{}{}", prompt, response.text);
+        errors = match parse_object(&response.text) {
+            Ok(args) => match parameters.validate(&args) {
+                Ok(()) => return Ok(Ok(args)),
+                Err(errors) => errors,
+            },
+            Err(err) => vec![err],
+        };
+        warn!(
+            "Invalid arguments for {} (attempt {}): {}",
+            name,
+            attempt,
+            errors.join("; ")
+        );
+    }
+    Ok(Err(errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::Mock;
+
+    const TEMPLATE: &str = "history: {{history}}\ndecision: {{decision_prompt}}\n\
+        choices:\n  - {{choices}}\nresponse: {{user_input}}\nchoice:";
+
+    const TREE: &str = r#"
+- id: start
+  text: "Hello"
+  choices:
+    - {choice: "Buy", next_id: buy}
+    - {choice: "Ask", next_id: ask}
+- id: buy
+  text: "What to buy?"
+  choices:
+    - {choice: "Book", next_id: book}
+    - {choice: "Movie", next_id: movie}
+- id: ask
+  text: "What is your question?"
+  choices: []
+- id: book
+  text: "How many?"
+  slots:
+    quantity: integer
+  choices:
+    - {choice: "Done", next_id: done}
+- id: movie
+  text: "Enjoy"
+  choices: []
+- id: done
+  text: "Bye"
+  choices: []
+"#;
+
+    fn state(tree: &str, mock: &Mock) -> DecisionState {
+        let nodes: Vec<Decision> = serde_yaml::from_str(tree).unwrap();
+        DecisionState::with_model(
+            Box::new(mock.clone()),
+            DecisionPromptTemplate::new(TEMPLATE.into()),
+            CompiledTree::new(nodes).unwrap(),
+        )
+    }
+
+    // Edits the node with the given id in a tree
+    fn edit(tree: &str, id: &str, f: impl FnOnce(&mut Decision)) -> String {
+        let mut nodes: Vec<Decision> = serde_yaml::from_str(tree).unwrap();
+        f(nodes.iter_mut().find(|node| node.id == id).unwrap());
+        serde_yaml::to_string(&nodes).unwrap()
+    }
+
+    async fn run(state: &DecisionState, session: &mut Session, input: &str) -> DecisionResult {
+        run_decision(Some(input.into()), state, session)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn matches_the_user_choice() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Ask").unwrap();
+        let state = state(TREE, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I have a question").await;
+        assert_eq!(result.outcome, ChoiceOutcome::Matched);
+        assert_eq!(result.choice.as_deref(), Some("Ask"));
+        assert_eq!(result.current_id, "ask");
+        assert_eq!(result.prediction_stop, Some(ChoiceOutcome::Terminal));
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn predicts_the_following_choices() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        mock.on("decision: What to buy", "Movie").unwrap();
+        let state = state(TREE, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I want to buy a movie").await;
+        assert_eq!(result.outcome, ChoiceOutcome::Matched);
+        assert_eq!(result.choice.as_deref(), Some("Buy"));
+        assert_eq!(result.current_id, "movie");
+        let predicted: Vec<&str> = result.predictions.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(predicted, ["buy", "movie"]);
+        assert_eq!(result.prediction_stop, Some(ChoiceOutcome::Terminal));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_outcome_of_the_answer_when_prediction_fails() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        mock.set_fallback("Something else");
+        let state = state(TREE, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I want to buy").await;
+        assert_eq!(result.outcome, ChoiceOutcome::Matched);
+        assert_eq!(result.prediction_stop, Some(ChoiceOutcome::NoMatch));
+        assert_eq!(result.current_id, "buy");
+        assert!(!result.is_ambiguous());
+    }
+
+    #[tokio::test]
+    async fn stops_predicting_at_a_node_without_prediction() {
+        let tree = edit(TREE, "buy", |node| node.predict = Some(false));
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        let state = state(&tree, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I want to buy a movie").await;
+        assert_eq!(result.current_id, "buy");
+        assert_eq!(
+            result.prediction_stop,
+            Some(ChoiceOutcome::PredictionDisabled)
+        );
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stops_predicting_at_the_depth_limit() {
+        let tree = edit(TREE, "start", |node| node.max_prediction_depth = Some(1));
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        mock.on("decision: What to buy", "Movie").unwrap();
+        let state = state(&tree, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I want to buy a movie").await;
+        assert_eq!(result.current_id, "buy");
+        assert_eq!(result.prediction_stop, Some(ChoiceOutcome::DepthLimit));
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resets_the_history() {
+        let tree = edit(TREE, "buy", |node| node.reset = Some(true));
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        mock.on("decision: What to buy", "Movie").unwrap();
+        let state = state(&tree, &mock);
+        let mut session = Session::new();
+
+        run(&state, &mut session, "I want to buy a movie").await;
+        // The answer to `start` is forgotten at `buy`, and predicted answers are not recorded
+        assert!(session.history.is_empty());
+        assert!(mock.prompts()[1].starts_with("history: \n"));
+    }
+
+    #[tokio::test]
+    async fn asks_for_slots_instead_of_predicting_them() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Buy").unwrap();
+        mock.on("decision: What to buy", "Book").unwrap();
+        let state = state(TREE, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "I want to buy 3 books").await;
+        assert_eq!(result.current_id, "book");
+        assert_eq!(result.prediction_stop, Some(ChoiceOutcome::SlotsRequired));
+        assert!(session.variables.is_empty());
+        assert_eq!(mock.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn asks_again_when_slots_cannot_be_extracted() {
+        let mock = Mock::default();
+        mock.set_fallback("No JSON here");
+        let state = state(TREE, &mock);
+        let mut session = Session::new();
+        session.current_id = "book".into();
+
+        let result = run(&state, &mut session, "Not sure").await;
+        assert_eq!(result.outcome, ChoiceOutcome::MissingSlots);
+        assert_eq!(result.missing_slots, ["quantity"]);
+        assert_eq!(result.current_id, "book");
+    }
+
+    #[tokio::test]
+    async fn asks_the_model_about_a_single_choice_with_an_else_edge() {
+        let tree = edit(TREE, "book", |node| {
+            node.slots = None;
+            node.else_id = Some("movie".into());
+        });
+        let mock = Mock::default();
+        mock.set_fallback("Something else");
+        let state = state(&tree, &mock);
+        let mut session = Session::new();
+        session.current_id = "book".into();
+
+        let result = run(&state, &mut session, "Show me movies").await;
+        assert_eq!(result.outcome, ChoiceOutcome::Fallback);
+        assert_eq!(result.current_id, "movie");
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn offers_only_the_choices_whose_guard_holds() {
+        let tree = edit(TREE, "start", |node| {
+            node.choices.as_mut().unwrap()[0].when = Some("vars.member".into());
+        });
+        let mock = Mock::default();
+        let state = state(&tree, &mock);
+        let mut session = Session::new();
+
+        // With the guarded choice hidden, the other one is taken without asking the model
+        let result = run(&state, &mut session, "Hi").await;
+        assert_eq!(result.outcome, ChoiceOutcome::SingleChoice);
+        assert_eq!(result.current_id, "ask");
+        assert!(mock.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shows_the_end_of_a_sub_tree_before_returning() {
+        let tree = r#"
+- id: start
+  call: {tree: feedback.yaml, return_to: done}
+- id: feedback::start
+  text: "Any feedback?"
+  choices:
+    - {choice: "Feedback", next_id: "feedback::thanks"}
+- id: feedback::thanks
+  text: "Thanks!"
+  choices: []
+- id: done
+  text: "Bye"
+  choices: []
+"#;
+        let mock = Mock::default();
+        let state = state(tree, &mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "It was great").await;
+        assert_eq!(result.outcome, ChoiceOutcome::SingleChoice);
+        assert_eq!(result.current_id, "feedback::thanks");
+        assert_eq!(session.call_stack, ["done"]);
+
+        let result = run(&state, &mut session, "You're welcome").await;
+        assert_eq!(result.outcome, ChoiceOutcome::Returned);
+        assert_eq!(result.current_id, "done");
+        assert!(session.call_stack.is_empty());
+    }
+
+    #[tokio::test]
+    async fn visits_a_call_node_the_session_starts_at() {
+        let tree = r#"
+- id: start
+  set: {survey: "feedback"}
+  call: {tree: feedback.yaml, return_to: done}
+- id: feedback::start
+  text: "Any {{vars.survey}}?"
+  choices: []
+- id: done
+  text: "Bye"
+  choices: []
+"#;
+        let mock = Mock::default();
+        let state = state(tree, &mock);
+        let mut session = Session::new();
+
+        let result = run_decision(None, &state, &mut session)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.current_id, "feedback::start");
+        assert_eq!(result.text, "Any feedback?");
+        assert_eq!(session.call_stack, ["done"]);
+    }
+
+    // Tool that responds with its arguments
+    struct Echo {
+        id: String,
+        parameters: Parameters,
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for Echo {
+        fn id(&self) -> &String {
+            &self.id
+        }
+
+        fn name(&self) -> &String {
+            &self.id
+        }
+
+        fn description(&self) -> &String {
+            &self.id
+        }
+
+        fn parameters(&self) -> Option<&Parameters> {
+            Some(&self.parameters)
+        }
+
+        async fn run(&self, args: &Value) -> Result<Option<ToolResponse>, CognitionError> {
+            Ok(Some(ToolResponse {
+                id: self.id.clone(),
+                response: args.to_string(),
+            }))
+        }
+    }
+
+    fn state_with_echo(mock: &Mock) -> DecisionState {
+        let tree = edit(TREE, "ask", |node| node.tool = Some("echo".into()));
+        let mut state = state(&tree, mock);
+        let parameters = "{properties: {city: {type: string}}, required: [city]}";
+        state.add_tool(Box::new(Echo {
+            id: "echo".into(),
+            parameters: serde_yaml::from_str(parameters).unwrap(),
+        }));
+        state
+    }
+
+    #[tokio::test]
+    async fn asks_again_for_invalid_tool_arguments() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Ask").unwrap();
+        mock.push_response(r#"{"city": 1}"#);
+        mock.push_response(r#"{"city": "Paris"}"#);
+        let state = state_with_echo(&mock);
+        let mut session = Session::new();
+
+        let result = run(&state, &mut session, "Weather in Paris?").await;
+        assert_eq!(result.current_id, "ask");
+        assert_eq!(
+            result.tool_response.unwrap().response,
+            r#"{"city":"Paris"}"#
+        );
+        let prompts = mock.prompts();
+        assert_eq!(prompts.len(), 3);
+        assert!(!prompts[1].contains("errors:"));
+        assert!(prompts[2].contains("Argument 'city' must be of type string, got 1"));
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_tool_arguments_are_valid() {
+        let mock = Mock::default();
+        mock.on("decision: Hello", "Ask").unwrap();
+        mock.set_fallback("{}");
+        let state = state_with_echo(&mock);
+        let mut session = Session::new();
+
+        let err = run_decision(Some("Weather?".into()), &state, &mut session)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.0,
+            "Unable to fill in the arguments of 'echo': Missing required argument 'city'"
+        );
+        assert_eq!(mock.calls().len(), 1 + state.engine.max_argument_attempts);
+    }
 }
@@ -0,0 +1,453 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+/// A guard expression such as `vars.age >= 18 && tools.wolfram_alpha.response != ""`,
+/// evaluated against the session context (see `Session::context`).
+///
+/// Expressions combine dotted paths, numbers, quoted strings, `true`, `false` and `null` with
+/// `==`, `!=`, `<`, `<=`, `>`, `>=`, `&&` (or `and`), `||` (or `or`), `!` (or `not`) and
+/// parentheses. A path that does not resolve is `null`. Strings holding a number compare as
+/// numbers against numbers. In a boolean position, `null`, `false`, `0`, `""` and empty
+/// arrays and objects are false.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression(Node);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Literal(Value),
+    Path(Vec<String>),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Box<Node>, Operator, Box<Node>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Why an expression could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpressionError {
+    /// Zero-based character offset of the problem.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            end: source.chars().count(),
+        };
+        let node = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self(node)),
+            Some((position, token)) => Err(ExpressionError {
+                position: *position,
+                message: format!("Unexpected {}", token),
+            }),
+        }
+    }
+
+    /// Evaluates the expression in a boolean position.
+    pub fn is_true(&self, context: &Value) -> bool {
+        truthy(&evaluate(&self.0, context))
+    }
+
+    /// Evaluates the expression to a value.
+    pub fn evaluate(&self, context: &Value) -> Value {
+        evaluate(&self.0, context)
+    }
+}
+
+fn evaluate(node: &Node, context: &Value) -> Value {
+    match node {
+        Node::Literal(value) => value.clone(),
+        Node::Path(path) => path
+            .iter()
+            .try_fold(context, |value, key| match value {
+                Value::Array(values) => values.get(key.parse::<usize>().ok()?),
+                _ => value.get(key),
+            })
+            .cloned()
+            .unwrap_or(Value::Null),
+        Node::Not(node) => Value::Bool(!truthy(&evaluate(node, context))),
+        Node::And(left, right) => {
+            Value::Bool(truthy(&evaluate(left, context)) && truthy(&evaluate(right, context)))
+        }
+        Node::Or(left, right) => {
+            Value::Bool(truthy(&evaluate(left, context)) || truthy(&evaluate(right, context)))
+        }
+        Node::Compare(left, operator, right) => {
+            let (left, right) = (evaluate(left, context), evaluate(right, context));
+            Value::Bool(match operator {
+                Operator::Eq => equals(&left, &right),
+                Operator::Ne => !equals(&left, &right),
+                Operator::Lt => compare(&left, &right) == Some(Ordering::Less),
+                Operator::Le => matches!(
+                    compare(&left, &right),
+                    Some(Ordering::Less | Ordering::Equal)
+                ),
+                Operator::Gt => compare(&left, &right) == Some(Ordering::Greater),
+                Operator::Ge => matches!(
+                    compare(&left, &right),
+                    Some(Ordering::Greater | Ordering::Equal)
+                ),
+            })
+        }
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(object) => !object.is_empty(),
+    }
+}
+
+// Numbers, and strings holding a number when compared with a number
+fn numbers(left: &Value, right: &Value) -> Option<(f64, f64)> {
+    let number = |value: &Value| match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+    if left.is_number() || right.is_number() {
+        Some((number(left)?, number(right)?))
+    } else {
+        None
+    }
+}
+
+fn equals(left: &Value, right: &Value) -> bool {
+    match numbers(left, right) {
+        Some((left, right)) => left == right,
+        None => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (numbers(left, right), left, right) {
+        (Some((left, right)), _, _) => left.partial_cmp(&right),
+        (None, Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(Value),
+    Path(Vec<String>),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Literal(value) => write!(f, "'{}'", value),
+            Token::Path(path) => write!(f, "'{}'", path.join(".")),
+            Token::Operator(operator) => write!(f, "'{}'", operator),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let chars: Vec<char> = source.chars().collect();
+    let error = |position: usize, message: String| ExpressionError { position, message };
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        // Each token with the number of characters it spans
+        let (token, len) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '&' if next == Some('&') => (Token::And, 2),
+            '|' if next == Some('|') => (Token::Or, 2),
+            '=' if next == Some('=') => (Token::Operator(Operator::Eq), 2),
+            '!' if next == Some('=') => (Token::Operator(Operator::Ne), 2),
+            '<' if next == Some('=') => (Token::Operator(Operator::Le), 2),
+            '>' if next == Some('=') => (Token::Operator(Operator::Ge), 2),
+            '!' => (Token::Not, 1),
+            '<' => (Token::Operator(Operator::Lt), 1),
+            '>' => (Token::Operator(Operator::Gt), 1),
+            '"' | '\'' => {
+                let mut text = String::new();
+                let mut end = i + 1;
+                loop {
+                    match chars.get(end) {
+                        None => return Err(error(i, "Unterminated string".into())),
+                        Some('\\') if end + 1 < chars.len() => {
+                            text.push(chars[end + 1]);
+                            end += 2;
+                        }
+                        Some(quote) if *quote == c => break,
+                        Some(other) => {
+                            text.push(*other);
+                            end += 1;
+                        }
+                    }
+                }
+                (Token::Literal(Value::String(text)), end + 1 - i)
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|c| c.is_ascii_digit())) => {
+                let mut end = i + 1;
+                while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '.') {
+                    end += 1;
+                }
+                let text: String = chars[i..end].iter().collect();
+                let number = match text.parse::<i64>() {
+                    Ok(number) => Value::from(number),
+                    Err(_) => text
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                        .map(Value::Number)
+                        .ok_or_else(|| error(i, format!("Invalid number '{}'", text)))?,
+                };
+                (Token::Literal(number), end - i)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (chars[end].is_alphanumeric() || chars[end] == '_' || chars[end] == '.')
+                {
+                    end += 1;
+                }
+                let word: String = chars[i..end].iter().collect();
+                let token = match word.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ if word.split('.').any(str::is_empty) => {
+                        return Err(error(i, format!("Invalid path '{}'", word)))
+                    }
+                    _ => Token::Path(word.split('.').map(str::to_owned).collect()),
+                };
+                (token, end - i)
+            }
+            other => return Err(error(i, format!("Unexpected character '{}'", other))),
+        };
+        tokens.push((i, token));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        if self.peek().map(|(_, next)| next) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.and()?;
+        while self.next_if(&Token::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.not()?;
+        while self.next_if(&Token::And) {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, ExpressionError> {
+        if self.next_if(&Token::Not) {
+            Ok(Node::Not(Box::new(self.not()?)))
+        } else {
+            self.compare()
+        }
+    }
+
+    fn compare(&mut self) -> Result<Node, ExpressionError> {
+        let left = self.primary()?;
+        match self.peek() {
+            Some((_, Token::Operator(operator))) => {
+                let operator = *operator;
+                self.position += 1;
+                let right = self.primary()?;
+                Ok(Node::Compare(Box::new(left), operator, Box::new(right)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        let (position, token) = match self.tokens.get(self.position) {
+            Some((position, token)) => (*position, token.clone()),
+            None => {
+                return Err(ExpressionError {
+                    position: self.end,
+                    message: "Unexpected end of expression".into(),
+                })
+            }
+        };
+        self.position += 1;
+        match token {
+            Token::Literal(value) => Ok(Node::Literal(value)),
+            Token::Path(path) => Ok(Node::Path(path)),
+            Token::Open => {
+                let node = self.or()?;
+                if self.next_if(&Token::Close) {
+                    Ok(node)
+                } else {
+                    Err(ExpressionError {
+                        position: self.peek().map_or(self.end, |(position, _)| *position),
+                        message: "Expected ')'".into(),
+                    })
+                }
+            }
+            token => Err(ExpressionError {
+                position,
+                message: format!("Unexpected {}", token),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(source: &str, context: &Value) -> bool {
+        Expression::parse(source).unwrap().is_true(context)
+    }
+
+    #[test]
+    fn compares_paths_with_literals() {
+        let context = json!({"vars": {"age": 21, "name": "Ann", "count": "3"}});
+        assert!(eval("vars.age >= 18", &context));
+        assert!(!eval("vars.age < 18", &context));
+        assert!(eval("vars.name == 'Ann'", &context));
+        assert!(eval(r#"vars.name != "Bob""#, &context));
+        assert!(eval("vars.count > 2", &context));
+        assert!(eval("vars.count == 3.0", &context));
+        assert!(eval("-1 < 0", &context));
+    }
+
+    #[test]
+    fn treats_missing_paths_as_null() {
+        let context = json!({"vars": {}});
+        assert!(eval("vars.missing == null", &context));
+        assert!(!eval("vars.missing", &context));
+        assert!(!eval("vars.missing > 0", &context));
+        assert!(!eval("vars.missing.deeper", &context));
+    }
+
+    #[test]
+    fn indexes_arrays() {
+        let context = json!({"vars": {"items": ["a", "b"]}});
+        assert!(eval("vars.items.1 == 'b'", &context));
+        assert!(eval("vars.items.2 == null", &context));
+    }
+
+    #[test]
+    fn combines_conditions() {
+        let context = json!({"vars": {"a": true, "b": false}});
+        assert!(eval("vars.a && !vars.b", &context));
+        assert!(eval("vars.a and not vars.b", &context));
+        assert!(eval("vars.b || vars.a", &context));
+        assert!(!eval("vars.b or not vars.a", &context));
+        // `&&` binds tighter than `||`
+        assert!(eval("vars.a || vars.b && vars.b", &context));
+        assert!(!eval("(vars.a || vars.b) && vars.b", &context));
+    }
+
+    #[test]
+    fn evaluates_truthiness() {
+        let context = json!({"vars": {"zero": 0, "empty": "", "list": [], "text": "x"}});
+        assert!(!eval("vars.zero", &context));
+        assert!(!eval("vars.empty", &context));
+        assert!(!eval("vars.list", &context));
+        assert!(eval("vars.text", &context));
+        assert_eq!(
+            Expression::parse("1 < 2").unwrap().evaluate(&context),
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn reports_the_position_of_errors() {
+        let error = |source: &str| Expression::parse(source).unwrap_err();
+        assert_eq!(error("vars.a ==").position, 9);
+        assert_eq!(error("vars.a = 1").position, 7);
+        assert_eq!(error("'open").message, "Unterminated string");
+        assert_eq!(error("(vars.a").message, "Expected ')'");
+        assert_eq!(error("vars..a").message, "Invalid path 'vars..a'");
+        assert_eq!(error("vars.a vars.b").message, "Unexpected 'vars.b'");
+    }
+}
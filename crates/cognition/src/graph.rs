@@ -0,0 +1,316 @@
+use crate::engine::{Choice, Decision};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Longest node text shown in a graph label
+const MAX_LABEL_TEXT: usize = 48;
+
+/// Renders the decision tree as a Graphviz DOT digraph.
+///
+/// Edges are labeled with the choice text and its `when` guard, if any; `else` edges are
+/// dashed. A call has a `call` edge to the sub-tree's start, if it is loaded, and a dashed
+/// `return` edge. Nodes with a tool are filled, nodes with `reset` get a double border and
+/// nodes with `predict: false` are dashed.
+pub fn to_dot(nodes: &[Decision]) -> String {
+    let mut dot = String::from("digraph decision_tree {\n");
+    dot.push_str("  node [shape=box, style=rounded];\n");
+
+    for node in nodes {
+        let mut label = escape_dot(&node.id);
+        if !node.text.trim().is_empty() {
+            label.push_str(&format!("\\n{}", escape_dot(&summary(node))));
+        }
+        let mut style = vec!["rounded"];
+        let mut attributes = vec![];
+        if let Some(tool) = &node.tool {
+            label.push_str(&format!("\\n[tool: {}]", escape_dot(tool)));
+            style.push("filled");
+            attributes.push("fillcolor=\"#dbeafe\"".to_owned());
+        }
+        if let Some(call) = &node.call {
+            label.push_str(&format!("\\n[call: {}]", escape_dot(&call.namespace())));
+        }
+        if node.reset == Some(true) {
+            attributes.push("peripheries=2".to_owned());
+        }
+        if node.predict == Some(false) {
+            style.push("dashed");
+        }
+        attributes.insert(0, format!("style=\"{}\"", style.join(",")));
+        attributes.insert(0, format!("label=\"{}\"", label));
+        let _ = writeln!(
+            dot,
+            "  \"{}\" [{}];",
+            escape_dot(&node.id),
+            attributes.join(", ")
+        );
+    }
+
+    for node in nodes {
+        for choice in node.all_choices() {
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\"];",
+                escape_dot(&node.id),
+                escape_dot(choice.next_id()),
+                escape_dot(&edge_label(choice))
+            );
+        }
+        if let Some(else_id) = &node.else_id {
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"else\", style=dashed];",
+                escape_dot(&node.id),
+                escape_dot(else_id)
+            );
+        }
+        if let Some(call) = &node.call {
+            if let Some(start) = call_target(nodes, node) {
+                let _ = writeln!(
+                    dot,
+                    "  \"{}\" -> \"{}\" [label=\"call\"];",
+                    escape_dot(&node.id),
+                    escape_dot(&start)
+                );
+            }
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"return\", style=dashed];",
+                escape_dot(&node.id),
+                escape_dot(&call.return_to)
+            );
+        }
+        if let Some(clarify) = clarify_target(nodes, node) {
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"clarify\", style=dotted];",
+                escape_dot(&node.id),
+                escape_dot(clarify)
+            );
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Renders the decision tree as a Mermaid flowchart.
+///
+/// Edges are labeled with the choice text and its `when` guard, if any; `else` and `return`
+/// edges are dotted. Nodes are assigned the `tool`, `reset` and `nopredict` classes.
+pub fn to_mermaid(nodes: &[Decision]) -> String {
+    let mut keys = MermaidKeys::default();
+    let mut mermaid = String::from("flowchart TD\n");
+
+    for node in nodes {
+        let mut label = node.id.clone();
+        if !node.text.trim().is_empty() {
+            label.push_str(&format!("<br/>{}", summary(node)));
+        }
+        if let Some(tool) = &node.tool {
+            label.push_str(&format!("<br/>[tool: {}]", tool));
+        }
+        if let Some(call) = &node.call {
+            label.push_str(&format!("<br/>[call: {}]", call.namespace()));
+        }
+        let _ = writeln!(
+            mermaid,
+            "  {}[\"{}\"]",
+            keys.key(&node.id),
+            escape_mermaid(&label)
+        );
+    }
+
+    for node in nodes {
+        let from = keys.key(&node.id);
+        for choice in node.all_choices() {
+            let to = keys.key(choice.next_id());
+            let _ = writeln!(
+                mermaid,
+                "  {} -->|\"{}\"| {}",
+                from,
+                escape_mermaid(&edge_label(choice)),
+                to
+            );
+        }
+        if let Some(else_id) = &node.else_id {
+            let _ = writeln!(mermaid, "  {} -.->|else| {}", from, keys.key(else_id));
+        }
+        if let Some(call) = &node.call {
+            if let Some(start) = call_target(nodes, node) {
+                let _ = writeln!(mermaid, "  {} -->|call| {}", from, keys.key(&start));
+            }
+            let _ = writeln!(
+                mermaid,
+                "  {} -.->|return| {}",
+                from,
+                keys.key(&call.return_to)
+            );
+        }
+        if let Some(clarify) = clarify_target(nodes, node) {
+            let _ = writeln!(mermaid, "  {} -.->|clarify| {}", from, keys.key(clarify));
+        }
+    }
+
+    mermaid.push_str("  classDef tool fill:#dbeafe\n");
+    mermaid.push_str("  classDef reset stroke-width:3px\n");
+    mermaid.push_str("  classDef nopredict stroke-dasharray:5 5\n");
+    for node in nodes {
+        let mut classes = vec![];
+        if node.tool.is_some() {
+            classes.push("tool");
+        }
+        if node.reset == Some(true) {
+            classes.push("reset");
+        }
+        if node.predict == Some(false) {
+            classes.push("nopredict");
+        }
+        if !classes.is_empty() {
+            let _ = writeln!(
+                mermaid,
+                "  class {} {}",
+                keys.key(&node.id),
+                classes.join(",")
+            );
+        }
+    }
+
+    mermaid
+}
+
+// Choice text, followed by its guard
+fn edge_label(choice: &Choice) -> String {
+    match &choice.when {
+        Some(when) => format!("{} [{}]", choice.text.trim(), when.trim()),
+        None => choice.text.trim().to_owned(),
+    }
+}
+
+// The node a `clarify` routes to, if it names a node
+fn clarify_target<'a>(nodes: &[Decision], node: &'a Decision) -> Option<&'a str> {
+    node.clarify
+        .as_deref()
+        .filter(|clarify| nodes.iter().any(|node| node.id == *clarify))
+}
+
+// The start node of the sub-tree a node calls, if it is loaded
+fn call_target(nodes: &[Decision], node: &Decision) -> Option<String> {
+    node.call
+        .as_ref()
+        .map(|call| call.start_id())
+        .filter(|start| nodes.iter().any(|node| node.id == *start))
+}
+
+// Node text, shortened for labels
+fn summary(node: &Decision) -> String {
+    let text = node.text.trim();
+    if text.chars().count() <= MAX_LABEL_TEXT {
+        text.to_owned()
+    } else {
+        let text: String = text.chars().take(MAX_LABEL_TEXT - 3).collect();
+        format!("{}...", text.trim_end())
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+        .replace('|', "#124;")
+        .replace('\n', "<br/>")
+}
+
+// Mermaid node keys are restricted, so node ids are mapped to n0, n1, ...
+#[derive(Default)]
+struct MermaidKeys(HashMap<String, String>);
+
+impl MermaidKeys {
+    fn key(&mut self, id: &str) -> String {
+        let next = format!("n{}", self.0.len());
+        self.0.entry(id.to_owned()).or_insert(next).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"
+- id: start
+  text: 'Say "hi" | wave'
+  reset: true
+  clarify: start
+  choices:
+    - {choice: "Yes | no", next_id: lookup, when: vars.ok}
+  else: survey
+- id: lookup
+  text: "A long text that goes on and on, well beyond the limit of a label"
+  tool: search
+  predict: false
+  choices: []
+- id: survey
+  call: {tree: feedback.yaml, return_to: lookup}
+- id: feedback::start
+  text: Rate
+  choices: []
+"#;
+
+    fn nodes() -> Vec<Decision> {
+        serde_yaml::from_str(TREE).unwrap()
+    }
+
+    #[test]
+    fn renders_dot() {
+        let expected = r##"digraph decision_tree {
+  node [shape=box, style=rounded];
+  "start" [label="start\nSay \"hi\" | wave", style="rounded", peripheries=2];
+  "lookup" [label="lookup\nA long text that goes on and on, well beyond...\n[tool: search]", style="rounded,filled,dashed", fillcolor="#dbeafe"];
+  "survey" [label="survey\n[call: feedback]", style="rounded"];
+  "feedback::start" [label="feedback::start\nRate", style="rounded"];
+  "start" -> "lookup" [label="Yes | no [vars.ok]"];
+  "start" -> "survey" [label="else", style=dashed];
+  "start" -> "start" [label="clarify", style=dotted];
+  "survey" -> "feedback::start" [label="call"];
+  "survey" -> "lookup" [label="return", style=dashed];
+}
+"##;
+        assert_eq!(to_dot(&nodes()), expected);
+    }
+
+    #[test]
+    fn renders_mermaid() {
+        let expected = r##"flowchart TD
+  n0["start<br/>Say #quot;hi#quot; #124; wave"]
+  n1["lookup<br/>A long text that goes on and on, well beyond...<br/>[tool: search]"]
+  n2["survey<br/>[call: feedback]"]
+  n3["feedback::start<br/>Rate"]
+  n0 -->|"Yes #124; no [vars.ok]"| n1
+  n0 -.->|else| n2
+  n0 -.->|clarify| n0
+  n2 -->|call| n3
+  n2 -.->|return| n1
+  classDef tool fill:#dbeafe
+  classDef reset stroke-width:3px
+  classDef nopredict stroke-dasharray:5 5
+  class n0 reset
+  class n1 tool,nopredict
+"##;
+        assert_eq!(to_mermaid(&nodes()), expected);
+    }
+
+    #[test]
+    fn leaves_out_calls_to_sub_trees_that_are_not_loaded() {
+        let nodes: Vec<Decision> =
+            serde_yaml::from_str("- {id: start, call: {tree: other.yaml, return_to: start}}")
+                .unwrap();
+        let dot = to_dot(&nodes);
+        assert!(!dot.contains("[label=\"call\"]"));
+        assert!(dot.contains("\"start\" -> \"start\" [label=\"return\", style=dashed];"));
+        assert!(!to_mermaid(&nodes).contains("|call|"));
+    }
+}
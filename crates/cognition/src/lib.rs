@@ -1,12 +1,32 @@
-mod config;
+pub mod config;
 mod engine;
-mod models;
+pub mod expression;
+pub mod graph;
+pub mod matching;
+pub mod models;
+pub mod parameters;
+pub mod session;
 mod templates;
 pub mod tools;
+pub mod tree;
+pub mod validation;
 
-pub use engine::{run_decision, Decision, DecisionResult, DecisionState};
-pub use templates::decision::DecisionPromptTemplate;
+pub use config::{CognitionConfig, ConfigError, Secret};
+pub use engine::{
+    run_decision, Call, Choice, ChoiceOutcome, Decision, DecisionResult, DecisionState, Prediction,
+    Slot,
+};
+pub use matching::{ChoiceMatch, ChoiceMatcher};
+pub use models::{GenerationParams, LargeLanguageModel, ModelRegistry, TokenStream};
+pub use parameters::{Parameter, ParameterType, Parameters};
+pub use session::Session;
+pub use templates::{arguments::ArgumentsPromptTemplate, decision::DecisionPromptTemplate};
 pub use tools::{Tool, ToolResponse};
+pub use tree::CompiledTree;
+pub use validation::{
+    lint_file, lint_template, lint_tree, tool_ids, validate_file, validate_nodes, validate_tree,
+    Diagnostic, Severity,
+};
 
 #[derive(Debug)]
 pub struct CognitionError(pub String);
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of matching a model response against a list of choices.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChoiceMatch {
+    /// Index of the matched choice.
+    pub index: usize,
+    /// Name of the strategy that produced the match.
+    pub strategy: String,
+    /// Similarity score between 0.0 and 1.0.
+    pub score: f32,
+}
+
+/// Strategy for mapping a model response to one of the available choices.
+pub trait ChoiceMatcher: Send + Sync {
+    /// Name reported in `ChoiceMatch::strategy`.
+    fn name(&self) -> &str;
+
+    /// Returns the best matching choice, if any.
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch>;
+}
+
+/// Default similarity threshold for `EditDistance`.
+pub const DEFAULT_THRESHOLD: f32 = 0.8;
+
+/// Reduces a response or choice to its comparable core: the first non-empty line,
+/// without list bullets, surrounding quotes, trailing punctuation or repeated whitespace.
+pub fn normalize(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let line = line
+        .trim_start_matches(['-', '*', '•'])
+        .trim()
+        .trim_matches(['"', '\'', '`'])
+        .trim_end_matches(['.', '!', '?', ','])
+        .trim();
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn matched(index: usize, strategy: &str, score: f32) -> ChoiceMatch {
+    ChoiceMatch {
+        index,
+        strategy: strategy.to_owned(),
+        score,
+    }
+}
+
+/// Exact comparison after normalization.
+pub struct NormalizedExact;
+
+impl ChoiceMatcher for NormalizedExact {
+    fn name(&self) -> &str {
+        "normalized_exact"
+    }
+
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch> {
+        let response = normalize(response);
+        choices
+            .iter()
+            .position(|choice| normalize(choice) == response)
+            .map(|index| matched(index, self.name(), 1.0))
+    }
+}
+
+/// Case-insensitive comparison after normalization.
+pub struct CaseInsensitive;
+
+impl ChoiceMatcher for CaseInsensitive {
+    fn name(&self) -> &str {
+        "case_insensitive"
+    }
+
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch> {
+        let response = normalize(response).to_lowercase();
+        choices
+            .iter()
+            .position(|choice| normalize(choice).to_lowercase() == response)
+            .map(|index| matched(index, self.name(), 1.0))
+    }
+}
+
+/// One-based choice number, e.g. "2" or "2." for the second choice.
+pub struct IndexNumber;
+
+impl ChoiceMatcher for IndexNumber {
+    fn name(&self) -> &str {
+        "index_number"
+    }
+
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch> {
+        let response = normalize(response);
+        let number = response.trim_end_matches(')').parse::<usize>().ok()?;
+        (1..=choices.len())
+            .contains(&number)
+            .then(|| matched(number - 1, self.name(), 1.0))
+    }
+}
+
+/// Closest choice by normalized Levenshtein similarity, if above the threshold.
+pub struct EditDistance {
+    pub threshold: f32,
+}
+
+impl EditDistance {
+    pub fn new(threshold: f32) -> Self {
+        Self { threshold }
+    }
+}
+
+impl ChoiceMatcher for EditDistance {
+    fn name(&self) -> &str {
+        "edit_distance"
+    }
+
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch> {
+        let response = normalize(response).to_lowercase();
+        choices
+            .iter()
+            .enumerate()
+            .map(|(index, choice)| {
+                let choice = normalize(choice).to_lowercase();
+                (index, similarity(&response, &choice))
+            })
+            .filter(|(_, score)| *score >= self.threshold)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, score)| matched(index, self.name(), score))
+    }
+}
+
+/// Tries each matcher in order and returns the first match.
+pub struct FirstMatch(pub Vec<Box<dyn ChoiceMatcher>>);
+
+impl FirstMatch {
+    /// Exact, case-insensitive, index number, then edit distance with the given threshold.
+    pub fn standard(threshold: f32) -> Self {
+        Self(vec![
+            Box::new(NormalizedExact),
+            Box::new(CaseInsensitive),
+            Box::new(IndexNumber),
+            Box::new(EditDistance::new(threshold)),
+        ])
+    }
+}
+
+impl Default for FirstMatch {
+    fn default() -> Self {
+        Self::standard(DEFAULT_THRESHOLD)
+    }
+}
+
+impl ChoiceMatcher for FirstMatch {
+    fn name(&self) -> &str {
+        "first_match"
+    }
+
+    fn find(&self, response: &str, choices: &[String]) -> Option<ChoiceMatch> {
+        self.0
+            .iter()
+            .find_map(|matcher| matcher.find(response, choices))
+    }
+}
+
+/// Similarity in `[0, 1]` derived from the Levenshtein distance.
+fn similarity(a: &str, b: &str) -> f32 {
+    let len = a.chars().count().max(b.chars().count());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f32 / len as f32
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choices(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn normalizes_responses() {
+        assert_eq!(normalize("\n  - \"Buy a book.\"  \nmore"), "Buy a book");
+        assert_eq!(normalize("* Ask   a   question?"), "Ask a question");
+        assert_eq!(normalize("`Exit!`"), "Exit");
+        assert_eq!(normalize("   "), "");
+    }
+
+    #[test]
+    fn matches_exactly_then_ignoring_case() {
+        let choices = choices(&["Buy", "buy", "Ask"]);
+        let exact = NormalizedExact.find("buy.", &choices).unwrap();
+        assert_eq!(
+            (exact.index, exact.strategy.as_str()),
+            (1, "normalized_exact")
+        );
+        assert!(NormalizedExact.find("ASK", &choices).is_none());
+        assert_eq!(CaseInsensitive.find("ASK", &choices).unwrap().index, 2);
+    }
+
+    #[test]
+    fn matches_choice_numbers() {
+        let choices = choices(&["Buy", "Ask"]);
+        assert_eq!(IndexNumber.find("2.", &choices).unwrap().index, 1);
+        assert_eq!(IndexNumber.find("1)", &choices).unwrap().index, 0);
+        assert!(IndexNumber.find("0", &choices).is_none());
+        assert!(IndexNumber.find("3", &choices).is_none());
+    }
+
+    #[test]
+    fn matches_close_responses_above_the_threshold() {
+        let choices = choices(&["Harry Potter", "Lord of the Rings"]);
+        let matcher = EditDistance::new(DEFAULT_THRESHOLD);
+        let close = matcher.find("Hary Poter", &choices).unwrap();
+        assert_eq!(close.index, 0);
+        assert!(close.score >= DEFAULT_THRESHOLD && close.score < 1.0);
+        assert!(matcher.find("Something else", &choices).is_none());
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn reports_the_first_strategy_that_matches() {
+        let choices = choices(&["Buy", "Ask"]);
+        let matcher = FirstMatch::default();
+        assert_eq!(
+            matcher.find("ask", &choices).unwrap().strategy,
+            "case_insensitive"
+        );
+        assert_eq!(
+            matcher.find("2", &choices).unwrap().strategy,
+            "index_number"
+        );
+        assert_eq!(
+            matcher.find("Asks", &choices).map(|m| m.strategy),
+            None,
+            "'Asks' is 75% similar to 'Ask', below the default threshold"
+        );
+        assert!(FirstMatch(vec![]).find("Buy", &choices).is_none());
+    }
+}
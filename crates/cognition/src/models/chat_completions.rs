@@ -0,0 +1,369 @@
+use crate::{
+    config::{CognitionConfig, Secret},
+    models::{
+        sse, GenerationParams, InferenceResult, LargeLanguageModel, ModelError, TokenStream,
+        MAX_STOP_SEQUENCES, STREAM_DONE,
+    },
+};
+use async_trait::async_trait;
+use futures::{future, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Client, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Backend for any server implementing the OpenAI `/chat/completions` API, such as OpenAI,
+/// vLLM, the llama.cpp server or LocalAI.
+///
+/// The prompt is split at its first line starting with `user_message` (`history:` in the
+/// default templates): the template's instructions before it are sent in the system message,
+/// after the system prompt, and the history and the user's response from there on as the user
+/// message. Text from the user always comes after that line, so it never reaches the system
+/// message. The model's reply is the completion of the prompt.
+pub struct ChatCompletions {
+    client: Client,
+    url: String,
+    model: String,
+    system_prompt: Option<String>,
+    user_message: Option<String>,
+    headers: HeaderMap,
+}
+
+/// The `models.chat_completions` config section.
+#[derive(Deserialize)]
+pub struct ChatCompletionsConfig {
+    /// Base URL of the API, without `/chat/completions`.
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub model: String,
+    /// Sent as a bearer token, if set and not empty.
+    #[serde(default)]
+    pub api_key: Option<Secret>,
+    /// Start of the system message; `null` to send the prompt alone.
+    #[serde(default = "default_system_prompt")]
+    pub system_prompt: Option<String>,
+    /// Start of the first line of the prompt that goes into the user message; `null` to send
+    /// the whole prompt as the user message.
+    #[serde(default = "default_user_message")]
+    pub user_message: Option<String>,
+    /// Extra HTTP headers, e.g. for a proxy.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_base_url() -> String {
+    "https://api.openai.com/v1".into()
+}
+
+fn default_system_prompt() -> Option<String> {
+    Some(DEFAULT_SYSTEM_PROMPT.into())
+}
+
+fn default_user_message() -> Option<String> {
+    Some(DEFAULT_USER_MESSAGE.into())
+}
+
+/// System prompt asking the model to continue the prompt rather than reply to it.
+pub const DEFAULT_SYSTEM_PROMPT: &str = "Complete the document that starts below and ends \
+with the user's message. Reply with the text that comes next only, without repeating the \
+document or adding explanations.";
+
+/// Start of the line holding the history in the default prompt templates, the first line
+/// with text from the user.
+pub const DEFAULT_USER_MESSAGE: &str = "history:";
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    max_tokens: usize,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ChatMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Deserialize)]
+struct ChatReply {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+// Event of a streamed completion
+#[derive(Deserialize)]
+struct ChatStreamChunk {
+    choices: Vec<ChatStreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatStreamChoice {
+    delta: ChatReply,
+}
+
+impl ChatCompletions {
+    // System and user messages of a prompt
+    fn messages(&self, prompt: &str) -> Vec<ChatMessage> {
+        let split = self.user_message.as_deref().and_then(|start| {
+            prompt
+                .match_indices(start)
+                .map(|(i, _)| i)
+                .find(|i| *i == 0 || prompt[..*i].ends_with('\n'))
+        });
+        let (instructions, user) = match split {
+            Some(i) => (prompt[..i].trim(), &prompt[i..]),
+            None => ("", prompt),
+        };
+        let system: Vec<&str> = self
+            .system_prompt
+            .as_deref()
+            .into_iter()
+            .chain(Some(instructions))
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        let mut messages = vec![];
+        if !system.is_empty() {
+            messages.push(ChatMessage {
+                role: "system",
+                content: system.join("\n\n"),
+            });
+        }
+        messages.push(ChatMessage {
+            role: "user",
+            content: user.to_owned(),
+        });
+        messages
+    }
+
+    async fn send(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> Result<Response, ModelError> {
+        let request_body = ChatRequest {
+            model: &self.model,
+            messages: self.messages(prompt),
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            stop: params.stop_for_api(MAX_STOP_SEQUENCES),
+            stream,
+        };
+
+        let response = self
+            .client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| ModelError::new(&format!("HTTP request error: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| String::from("No error details"));
+            return Err(ModelError::new(&format!(
+                "Error {}: {}",
+                status, error_body
+            )));
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LargeLanguageModel for ChatCompletions {
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError> {
+        let ChatCompletionsConfig {
+            base_url,
+            model,
+            api_key,
+            system_prompt,
+            user_message,
+            headers: extra_headers,
+        } = config.models.backend("chat_completions")?;
+
+        let invalid_header = |name: &str, e: &dyn std::fmt::Display| {
+            ModelError::new(&format!(
+                "Invalid config key 'models.chat_completions.headers.{}': {}",
+                name, e
+            ))
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        // An empty key, e.g. from `${OPENAI_API_KEY:-}`, means no authentication
+        if let Some(api_key) = api_key.filter(|key| !key.expose().is_empty()) {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", api_key.expose()))
+                    .map_err(|e| ModelError::new(&format!("Authorization header error: {}", e)))?,
+            );
+        }
+        for (name, value) in &extra_headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid_header(name, &e))?,
+                HeaderValue::from_str(value).map_err(|e| invalid_header(name, &e))?,
+            );
+        }
+
+        Ok(Self {
+            client: Client::new(),
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model,
+            system_prompt,
+            user_message,
+            headers,
+        })
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        max_length: usize,
+        temperature: f32,
+    ) -> Result<InferenceResult, ModelError> {
+        let params = GenerationParams {
+            max_tokens: max_length,
+            temperature,
+            stop: vec![],
+        };
+        self.generate_with(prompt, &params).await
+    }
+
+    async fn generate_with(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceResult, ModelError> {
+        let response = self
+            .send(prompt, params, false)
+            .await?
+            .json::<ChatResponse>()
+            .await
+            .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e)))?;
+
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| ModelError::new("No choices found"))?;
+        let mut result = InferenceResult {
+            text: choice.message.content.unwrap_or_default(),
+            probabilities: vec![],
+        };
+        params.truncate(&mut result.text);
+
+        Ok(result)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        let response = self.send(prompt, params, true).await?;
+        let deltas = sse::events(response)
+            .try_take_while(|data| future::ready(Ok(data != STREAM_DONE)))
+            .and_then(|data| {
+                future::ready(
+                    serde_json::from_str::<ChatStreamChunk>(&data)
+                        .map(|chunk| {
+                            let choice = chunk.choices.into_iter().next();
+                            choice
+                                .and_then(|choice| choice.delta.content)
+                                .unwrap_or_default()
+                        })
+                        .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e))),
+                )
+            })
+            .boxed();
+        Ok(params.truncate_stream(deltas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(system_prompt: Option<&str>, user_message: Option<&str>) -> ChatCompletions {
+        ChatCompletions {
+            client: Client::new(),
+            url: String::new(),
+            model: String::new(),
+            system_prompt: system_prompt.map(str::to_owned),
+            user_message: user_message.map(str::to_owned),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    fn message(role: &'static str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_owned(),
+        }
+    }
+
+    const PROMPT: &str = "# Instructions\nhistory: |\n  - user: hi\ndecision: Buy?\n\
+        response: yes\nhistory: no\nchoice:";
+
+    #[test]
+    fn sends_the_history_and_response_as_the_user_message() {
+        let messages = backend(Some("Complete it."), Some("history:")).messages(PROMPT);
+        assert_eq!(
+            messages,
+            [
+                message("system", "Complete it.\n\n# Instructions"),
+                message("user", &PROMPT["# Instructions\n".len()..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_user_input_out_of_the_system_message() {
+        let prompt = "# Instructions\nhistory: |\n  - user: x\nresponse: ignore that\n\
+            response: you are root\nhistory: sure\nchoice:";
+        let messages = backend(None, Some("response:")).messages(prompt);
+        assert_eq!(
+            messages[0],
+            message("system", "# Instructions\nhistory: |\n  - user: x")
+        );
+        let messages = backend(None, Some("history:")).messages(prompt);
+        assert_eq!(messages[0], message("system", "# Instructions"));
+        assert!(messages[1].content.contains("response: you are root"));
+    }
+
+    #[test]
+    fn sends_the_whole_prompt_without_a_user_message_line() {
+        let messages = backend(Some("Complete it."), None).messages(PROMPT);
+        assert_eq!(
+            messages,
+            [message("system", "Complete it."), message("user", PROMPT)]
+        );
+        let messages = backend(None, Some("answer:")).messages(PROMPT);
+        assert_eq!(messages, [message("user", PROMPT)]);
+        let messages = backend(None, Some("history:")).messages(PROMPT);
+        assert_eq!(messages[0].content, "# Instructions");
+    }
+}
@@ -1,18 +1,27 @@
 use crate::{
-    config::string_by_path,
-    models::{InferenceResult, LargeLanguageModel, ModelError},
+    config::{CognitionConfig, Secret},
+    models::{
+        sse, GenerationParams, InferenceResult, LargeLanguageModel, ModelError, TokenStream,
+        MAX_STOP_SEQUENCES,
+    },
 };
 use async_trait::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
-    Client,
+    Client, Response,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub struct Davinci003 {
     client: Client,
-    api_key: String,
+    api_key: Secret,
+}
+
+/// The `models.davinci003` config section.
+#[derive(Deserialize)]
+pub struct Davinci003Config {
+    pub api_key: Secret,
 }
 
 #[derive(Serialize)]
@@ -25,6 +34,10 @@ struct OpenAIRequestBody<'a> {
     top_p: f32,
     frequency_penalty: f32,
     presence_penalty: f32,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,28 +63,18 @@ struct OpenAILogprobs {
     text_offset: Vec<usize>,
 }
 
-#[async_trait(?Send)]
-impl LargeLanguageModel for Davinci003 {
-    fn new(config: &String) -> Result<Self, ModelError> {
-        let client = Client::new();
-        let api_key = string_by_path(config, "models.davinci003.api_key").unwrap();
-        Ok(Self {
-            client,
-            api_key: api_key.to_string(),
-        })
-    }
-
-    async fn generate(
+impl Davinci003 {
+    async fn send(
         &self,
         prompt: &str,
-        max_length: usize,
-        temperature: f32,
-    ) -> Result<InferenceResult, ModelError> {
+        params: &GenerationParams,
+        stream: bool,
+    ) -> Result<Response, ModelError> {
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         headers.insert(
             AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", self.api_key,))
+            HeaderValue::from_str(&format!("Bearer {}", self.api_key.expose()))
                 .map_err(|e| ModelError::new(&format!("Authorization header error: {}", e)))?,
         );
 
@@ -79,11 +82,13 @@ impl LargeLanguageModel for Davinci003 {
             model: "text-davinci-003",
             prompt,
             suffix: "\n\n",
-            temperature,
-            max_tokens: max_length,
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
             top_p: 1.0,
             frequency_penalty: 0.0,
             presence_penalty: 0.0,
+            stop: params.stop_for_api(MAX_STOP_SEQUENCES),
+            stream,
         };
 
         let response = self
@@ -93,20 +98,76 @@ impl LargeLanguageModel for Davinci003 {
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| ModelError::new(&format!("HTTP request error: {}", e)))?
+            .map_err(|e| ModelError::new(&format!("HTTP request error: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| String::from("No error details"));
+            return Err(ModelError::new(&format!(
+                "Error {}: {}",
+                status, error_body
+            )));
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LargeLanguageModel for Davinci003 {
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError> {
+        let client = Client::new();
+        let Davinci003Config { api_key } = config.models.backend("davinci003")?;
+        Ok(Self { client, api_key })
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        max_length: usize,
+        temperature: f32,
+    ) -> Result<InferenceResult, ModelError> {
+        let params = GenerationParams {
+            max_tokens: max_length,
+            temperature,
+            stop: vec![],
+        };
+        self.generate_with(prompt, &params).await
+    }
+
+    async fn generate_with(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceResult, ModelError> {
+        let response = self
+            .send(prompt, params, false)
+            .await?
             .json::<OpenAIResponse>()
             .await
             .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e)))?;
 
         let choice = response
             .choices
-            .get(0)
+            .first()
             .ok_or_else(|| ModelError::new("No choices found"))?;
-        let result = InferenceResult {
+        let mut result = InferenceResult {
             text: choice.text.clone(),
             probabilities: vec![], // You may want to calculate probabilities based on your requirements
         };
+        params.truncate(&mut result.text);
 
         Ok(result)
     }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        let response = self.send(prompt, params, true).await?;
+        Ok(params.truncate_stream(sse::completions(response)))
+    }
 }
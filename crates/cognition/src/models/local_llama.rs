@@ -0,0 +1,242 @@
+use crate::{
+    config::{CognitionConfig, ConfigError},
+    models::{GenerationParams, InferenceResult, LargeLanguageModel, ModelError, TokenStream},
+};
+use async_trait::async_trait;
+use candle_core::{quantized::gguf_file, Device, Tensor};
+use candle_transformers::{
+    generation::LogitsProcessor,
+    models::quantized_llama::{ModelWeights, MAX_SEQ_LEN},
+};
+use futures::{channel::mpsc, StreamExt};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+/// In-process backend running a quantized LLaMA model from a GGUF file on the CPU, with no
+/// network access. Available with the `local-llama` cargo feature.
+///
+/// Generation runs on a dedicated thread pool; one prompt is processed at a time.
+pub struct LocalLlama {
+    inner: Arc<Inner>,
+}
+
+/// The `models.local_llama` config section.
+#[derive(Deserialize)]
+pub struct LocalLlamaConfig {
+    /// Path of the GGUF model file.
+    pub model: PathBuf,
+    /// Path of the model's `tokenizer.json`.
+    pub tokenizer: PathBuf,
+    /// Maximum number of tokens of the prompt and the response together.
+    #[serde(default = "default_context_size")]
+    pub context_size: usize,
+    /// Number of inference threads; defaults to the number of CPUs.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    /// Seed of the sampler, for reproducible responses.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_context_size() -> usize {
+    2048
+}
+
+struct Inner {
+    weights: Mutex<ModelWeights>,
+    tokenizer: Tokenizer,
+    eos_token: Option<u32>,
+    context_size: usize,
+    seed: u64,
+    pool: rayon::ThreadPool,
+}
+
+fn model_error(err: impl Display) -> ModelError {
+    ModelError::new(&format!("Inference error: {}", err))
+}
+
+impl Inner {
+    // Generates a response, passing each delta to `on_delta` until it returns false
+    fn complete(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+        mut on_delta: impl FnMut(&str) -> bool + Send,
+    ) -> Result<(), ModelError> {
+        if params.max_tokens >= self.context_size {
+            return Err(ModelError::new(&format!(
+                "max_tokens ({}) must be smaller than models.local_llama.context_size ({})",
+                params.max_tokens, self.context_size
+            )));
+        }
+        let encoding = self.tokenizer.encode(prompt, true).map_err(model_error)?;
+        let mut input = encoding.get_ids().to_vec();
+        // Keep the end of a prompt that does not fit, as it holds the question
+        let budget = self.context_size - params.max_tokens;
+        if input.len() > budget {
+            input.drain(..input.len() - budget);
+        }
+
+        let mut guard = self
+            .weights
+            .lock()
+            .map_err(|_| ModelError::new("Model is unusable after a failed generation"))?;
+        let weights = &mut *guard;
+        let temperature = Some(params.temperature as f64).filter(|t| *t > 0.0);
+        let mut sampler = LogitsProcessor::new(self.seed, temperature, None);
+        let mut position = 0;
+        let mut generated = vec![];
+        let mut emitted = 0;
+
+        self.pool.install(|| {
+            for _ in 0..params.max_tokens {
+                let tokens = Tensor::new(input.as_slice(), &Device::Cpu)
+                    .and_then(|tokens| tokens.unsqueeze(0))
+                    .map_err(model_error)?;
+                // Position 0 also clears the cache of the previous prompt
+                let logits = weights
+                    .forward(&tokens, position)
+                    .and_then(|logits| logits.squeeze(0))
+                    .map_err(model_error)?;
+                position += input.len();
+                let token = sampler.sample(&logits).map_err(model_error)?;
+                if Some(token) == self.eos_token {
+                    break;
+                }
+                generated.push(token);
+                input = vec![token];
+
+                // Tokens are decoded together, as a character may span several of them
+                let text = self
+                    .tokenizer
+                    .decode(&generated, true)
+                    .map_err(model_error)?;
+                let delta = text
+                    .get(emitted..)
+                    .filter(|delta| !delta.is_empty() && !delta.ends_with('\u{FFFD}'));
+                if let Some(delta) = delta {
+                    if !on_delta(delta) {
+                        break;
+                    }
+                    emitted = text.len();
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl LargeLanguageModel for LocalLlama {
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError> {
+        let LocalLlamaConfig {
+            model,
+            tokenizer,
+            context_size,
+            threads,
+            seed,
+        } = config.models.backend("local_llama")?;
+        if context_size == 0 || context_size > MAX_SEQ_LEN {
+            return Err(ConfigError::Invalid {
+                key: "models.local_llama.context_size".into(),
+                message: format!("must be between 1 and {}", MAX_SEQ_LEN),
+            }
+            .into());
+        }
+
+        let load_error = |path: &PathBuf, err: &dyn Display| {
+            ModelError::new(&format!("Unable to load {}: {}", path.display(), err))
+        };
+        let mut file = std::fs::File::open(&model).map_err(|err| load_error(&model, &err))?;
+        let content =
+            gguf_file::Content::read(&mut file).map_err(|err| load_error(&model, &err))?;
+        let eos_token = content
+            .metadata
+            .get("tokenizer.ggml.eos_token_id")
+            .and_then(|id| id.to_u32().ok());
+        let weights = ModelWeights::from_gguf(content, &mut file, &Device::Cpu)
+            .map_err(|err| load_error(&model, &err))?;
+        let tokenizer =
+            Tokenizer::from_file(&tokenizer).map_err(|err| load_error(&tokenizer, err.as_ref()))?;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.unwrap_or(0))
+            .build()
+            .map_err(|err| ModelError::new(&format!("Unable to start threads: {}", err)))?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                weights: Mutex::new(weights),
+                tokenizer,
+                eos_token,
+                context_size,
+                seed,
+                pool,
+            }),
+        })
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        max_length: usize,
+        temperature: f32,
+    ) -> Result<InferenceResult, ModelError> {
+        let params = GenerationParams {
+            max_tokens: max_length,
+            temperature,
+            stop: vec![],
+        };
+        self.generate_with(prompt, &params).await
+    }
+
+    async fn generate_with(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceResult, ModelError> {
+        let inner = self.inner.clone();
+        let prompt = prompt.to_owned();
+        let params = params.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut text = String::new();
+            inner.complete(&prompt, &params, |delta| {
+                text.push_str(delta);
+                // No need to generate past a stop sequence
+                let mut truncated = text.clone();
+                params.truncate(&mut truncated);
+                truncated.len() == text.len()
+            })?;
+            params.truncate(&mut text);
+            Ok(InferenceResult {
+                text,
+                probabilities: vec![],
+            })
+        })
+        .await
+        .map_err(model_error)?
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        let inner = self.inner.clone();
+        let prompt = prompt.to_owned();
+        let (sender, receiver) = mpsc::unbounded();
+        let generation = params.clone();
+        tokio::task::spawn_blocking(move || {
+            // Generation stops once the stream is dropped
+            let result = inner.complete(&prompt, &generation, |delta| {
+                sender.unbounded_send(Ok(delta.to_owned())).is_ok()
+            });
+            if let Err(err) = result {
+                let _ = sender.unbounded_send(Err(err));
+            }
+        });
+        Ok(params.truncate_stream(receiver.boxed()))
+    }
+}
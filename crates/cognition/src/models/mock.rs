@@ -0,0 +1,254 @@
+use crate::{
+    config::CognitionConfig,
+    models::{GenerationParams, InferenceResult, LargeLanguageModel, ModelError, TokenStream},
+};
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Scripted model that returns canned responses without any network access.
+///
+/// Responses are resolved in order: the first rule whose pattern matches the prompt,
+/// then the next queued response, then the fallback. Clones share the same script and
+/// call log, so a clone can be kept to inspect the prompts after handing the model over.
+#[derive(Clone, Default)]
+pub struct Mock {
+    inner: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<(Regex, String)>,
+    queue: VecDeque<String>,
+    fallback: Option<String>,
+    calls: Vec<MockCall>,
+}
+
+/// A single `generate` call received by the mock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub prompt: String,
+    pub max_length: usize,
+    pub temperature: f32,
+}
+
+/// The `models.mock` config section.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MockConfig {
+    pub responses: Vec<String>,
+    pub rules: Vec<MockRule>,
+    pub fallback: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MockRule {
+    pub pattern: String,
+    pub response: String,
+}
+
+impl Mock {
+    /// Creates a mock that answers with the given responses, in order.
+    pub fn with_responses<I, S>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mock = Self::default();
+        for response in responses {
+            mock.push_response(response);
+        }
+        mock
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues a response for the next call that no rule matches.
+    pub fn push_response(&self, response: impl Into<String>) {
+        self.state().queue.push_back(response.into());
+    }
+
+    /// Answers every prompt matching `pattern` with `response`.
+    pub fn on(&self, pattern: &str, response: impl Into<String>) -> Result<(), ModelError> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| ModelError::new(&format!("Invalid mock pattern '{}': {}", pattern, e)))?;
+        self.state().rules.push((regex, response.into()));
+        Ok(())
+    }
+
+    /// Answers with `response` once rules and queue are exhausted.
+    pub fn set_fallback(&self, response: impl Into<String>) {
+        self.state().fallback = Some(response.into());
+    }
+
+    /// Every call received so far.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state().calls.clone()
+    }
+
+    /// Every prompt received so far.
+    pub fn prompts(&self) -> Vec<String> {
+        self.state()
+            .calls
+            .iter()
+            .map(|call| call.prompt.clone())
+            .collect()
+    }
+
+    /// Number of queued responses not yet consumed.
+    pub fn remaining(&self) -> usize {
+        self.state().queue.len()
+    }
+}
+
+#[async_trait]
+impl LargeLanguageModel for Mock {
+    /// Reads `models.mock.responses`, `models.mock.rules` (`pattern`/`response` pairs)
+    /// and `models.mock.fallback` from the config.
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError> {
+        let MockConfig {
+            responses,
+            rules,
+            fallback,
+        } = config.models.backend_or_default("mock")?;
+
+        let mock = Self::with_responses(responses);
+        for rule in rules {
+            mock.on(&rule.pattern, rule.response)?;
+        }
+        if let Some(fallback) = fallback {
+            mock.set_fallback(fallback);
+        }
+        Ok(mock)
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        max_length: usize,
+        temperature: f32,
+    ) -> Result<InferenceResult, ModelError> {
+        let mut state = self.state();
+        state.calls.push(MockCall {
+            prompt: prompt.to_owned(),
+            max_length,
+            temperature,
+        });
+
+        let text = state
+            .rules
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(prompt))
+            .map(|(_, response)| response.clone())
+            .or_else(|| state.queue.pop_front())
+            .or_else(|| state.fallback.clone())
+            .ok_or_else(|| ModelError::new("Mock has no response for prompt"))?;
+
+        Ok(InferenceResult {
+            text,
+            probabilities: vec![],
+        })
+    }
+
+    /// Streams the response one word at a time, each with its trailing whitespace.
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        let result = self.generate_with(prompt, params).await?;
+        let words: Vec<Result<String, ModelError>> = result
+            .text
+            .split_inclusive(char::is_whitespace)
+            .map(|word| Ok(word.to_owned()))
+            .collect();
+        Ok(stream::iter(words).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn generate(mock: &Mock, prompt: &str) -> Result<String, String> {
+        block_on(mock.generate(prompt, 10, 0.5))
+            .map(|result| result.text)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn answers_from_rules_then_queue_then_fallback() {
+        let mock = Mock::with_responses(["first", "second"]);
+        mock.on("^decision: Buy", "Book").unwrap();
+        mock.set_fallback("fallback");
+
+        // A matching rule answers every time without consuming the queue
+        assert_eq!(generate(&mock, "decision: Buy?").unwrap(), "Book");
+        assert_eq!(generate(&mock, "decision: Buy now?").unwrap(), "Book");
+        assert_eq!(mock.remaining(), 2);
+
+        assert_eq!(generate(&mock, "other").unwrap(), "first");
+        assert_eq!(generate(&mock, "other").unwrap(), "second");
+        assert_eq!(mock.remaining(), 0);
+        assert_eq!(generate(&mock, "other").unwrap(), "fallback");
+        assert_eq!(generate(&mock, "other").unwrap(), "fallback");
+    }
+
+    #[test]
+    fn fails_without_a_response() {
+        let mock = Mock::default();
+        assert!(generate(&mock, "prompt").is_err());
+        assert!(mock.on("(", "x").is_err());
+    }
+
+    #[test]
+    fn logs_calls_shared_between_clones() {
+        let mock = Mock::with_responses(["a"]);
+        let clone = mock.clone();
+        block_on(clone.generate("prompt", 10, 0.5)).unwrap();
+        assert_eq!(
+            mock.calls(),
+            [MockCall {
+                prompt: "prompt".into(),
+                max_length: 10,
+                temperature: 0.5,
+            }]
+        );
+        assert_eq!(mock.prompts(), ["prompt"]);
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[test]
+    fn reads_the_script_from_the_config() {
+        let config = CognitionConfig::from_yaml(
+            "models:\n  mock:\n    responses: [queued]\n    \
+             rules: [{pattern: hello, response: hi}]\n    fallback: bye",
+        )
+        .unwrap();
+        let mock = Mock::new(&config).unwrap();
+        assert_eq!(generate(&mock, "hello").unwrap(), "hi");
+        assert_eq!(generate(&mock, "x").unwrap(), "queued");
+        assert_eq!(generate(&mock, "x").unwrap(), "bye");
+    }
+
+    #[test]
+    fn streams_one_word_at_a_time() {
+        let mock = Mock::with_responses(["Buy the book\nnow"]);
+        let params = GenerationParams {
+            max_tokens: 10,
+            temperature: 0.5,
+            stop: vec![],
+        };
+        let deltas: Vec<String> = block_on(async {
+            let stream = mock.generate_stream("prompt", &params).await.unwrap();
+            stream.map(Result::unwrap).collect().await
+        });
+        assert_eq!(deltas, ["Buy ", "the ", "book\n", "now"]);
+    }
+}
@@ -1,8 +1,16 @@
+use crate::config::{CognitionConfig, ConfigError};
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{self, Display};
 
+pub mod chat_completions;
 pub mod davinci003;
+#[cfg(feature = "local-llama")]
+pub mod local_llama;
+pub mod mock;
+mod sse;
 pub mod textgen;
 
 #[derive(Debug)]
@@ -26,16 +34,118 @@ impl Display for ModelError {
 
 impl Error for ModelError {}
 
+impl From<ConfigError> for ModelError {
+    fn from(err: ConfigError) -> Self {
+        ModelError::new(&err.to_string())
+    }
+}
+
 #[derive(Debug)]
 pub struct InferenceResult {
     pub text: String,
     pub probabilities: Vec<f32>,
 }
 
-#[async_trait(?Send)]
-pub trait LargeLanguageModel {
+/// Text generated by a model as it arrives, one delta at a time.
+pub type TokenStream = BoxStream<'static, Result<String, ModelError>>;
+
+/// Parameters of a single generation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationParams {
+    pub max_tokens: usize,
+    pub temperature: f32,
+    /// Sequences that end the response; the response does not include them.
+    pub stop: Vec<String>,
+}
+
+// OpenAI's APIs accept at most four stop sequences
+pub(crate) const MAX_STOP_SEQUENCES: usize = 4;
+
+// Data of the server-sent event that ends an OpenAI stream
+pub(crate) const STREAM_DONE: &str = "[DONE]";
+
+impl GenerationParams {
+    // Stop sequences for an API that accepts at most `max` of them. Responses are still cut
+    // with `truncate`, which also applies the sequences beyond those.
+    pub(crate) fn stop_for_api(&self, max: usize) -> &[String] {
+        &self.stop[..self.stop.len().min(max)]
+    }
+
+    /// Cuts the text at the first occurrence of a stop sequence.
+    pub fn truncate(&self, text: &mut String) {
+        let end = self
+            .stop
+            .iter()
+            .filter(|stop| !stop.is_empty())
+            .filter_map(|stop| text.find(stop.as_str()))
+            .min();
+        if let Some(end) = end {
+            text.truncate(end);
+        }
+    }
+
+    /// Ends a stream of deltas at the first stop sequence. Text that may be the start of a
+    /// stop sequence is held back until the following deltas tell.
+    pub fn truncate_stream(&self, deltas: TokenStream) -> TokenStream {
+        let stop: Vec<String> = self
+            .stop
+            .iter()
+            .filter(|stop| !stop.is_empty())
+            .cloned()
+            .collect();
+        if stop.is_empty() {
+            return deltas;
+        }
+        let state = (deltas, String::new(), false);
+        stream::unfold(state, move |(mut deltas, mut pending, done)| {
+            let stop = stop.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    match deltas.next().await {
+                        Some(Ok(delta)) => pending.push_str(&delta),
+                        Some(Err(err)) => return Some((Err(err), (deltas, pending, true))),
+                        None if pending.is_empty() => return None,
+                        None => return Some((Ok(pending), (deltas, String::new(), true))),
+                    }
+                    let end = stop
+                        .iter()
+                        .filter_map(|stop| pending.find(stop.as_str()))
+                        .min();
+                    match end {
+                        Some(0) => return None,
+                        Some(end) => {
+                            pending.truncate(end);
+                            return Some((Ok(pending), (deltas, String::new(), true)));
+                        }
+                        None => {}
+                    }
+                    let held = stop_prefix(&pending, &stop);
+                    if held > 0 {
+                        let rest = pending.split_off(held);
+                        return Some((Ok(pending), (deltas, rest, false)));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+// Start of the longest suffix of the text that begins a stop sequence
+fn stop_prefix(text: &str, stop: &[String]) -> usize {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .find(|i| stop.iter().any(|stop| stop.starts_with(&text[*i..])))
+        .unwrap_or(text.len())
+}
+
+#[async_trait]
+pub trait LargeLanguageModel: Send + Sync {
     /// Initializes the model with the given configuration.
-    fn new(config: &String) -> Result<Self, ModelError>
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError>
     where
         Self: Sized;
 
@@ -46,4 +156,176 @@ pub trait LargeLanguageModel {
         max_length: usize,
         temperature: f32,
     ) -> Result<InferenceResult, ModelError>;
+
+    /// Generates a response with the given parameters. The default calls `generate` and cuts
+    /// the response at the first stop sequence; backends that support stop sequences can
+    /// pass them on instead.
+    async fn generate_with(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceResult, ModelError> {
+        let mut result = self
+            .generate(prompt, params.max_tokens, params.temperature)
+            .await?;
+        params.truncate(&mut result.text);
+        Ok(result)
+    }
+
+    /// Generates a response with the given parameters as a stream of text deltas. The default
+    /// yields the whole response of `generate_with` at once.
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        let result = self.generate_with(prompt, params).await?;
+        Ok(stream::once(async move { Ok(result.text) }).boxed())
+    }
+}
+
+/// Constructs a boxed model from the given configuration.
+pub type ModelConstructor = fn(&CognitionConfig) -> Result<Box<dyn LargeLanguageModel>, ModelError>;
+
+/// Backend used when the configuration does not set `models.default`.
+pub const DEFAULT_MODEL: &str = "davinci003";
+
+fn construct<M: LargeLanguageModel + 'static>(
+    config: &CognitionConfig,
+) -> Result<Box<dyn LargeLanguageModel>, ModelError> {
+    Ok(Box::new(M::new(config)?))
+}
+
+/// Registry of model backends, keyed by the name used in `models.default`.
+pub struct ModelRegistry {
+    constructors: HashMap<String, ModelConstructor>,
+}
+
+impl ModelRegistry {
+    /// Creates a registry with no backends registered.
+    pub fn empty() -> Self {
+        Self {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Registers a backend constructor under the given name, replacing any previous one.
+    pub fn register(&mut self, name: &str, constructor: ModelConstructor) {
+        self.constructors.insert(name.to_owned(), constructor);
+    }
+
+    /// Registers a `LargeLanguageModel` implementation under the given name.
+    pub fn register_model<M: LargeLanguageModel + 'static>(&mut self, name: &str) {
+        self.register(name, construct::<M>);
+    }
+
+    /// Names of all registered backends, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.constructors.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Creates the named backend.
+    pub fn create(
+        &self,
+        name: &str,
+        config: &CognitionConfig,
+    ) -> Result<Box<dyn LargeLanguageModel>, ModelError> {
+        let constructor = self.constructors.get(name).ok_or_else(|| {
+            ModelError::new(&format!(
+                "Unknown model '{}', expected one of: {}",
+                name,
+                self.names().join(", ")
+            ))
+        })?;
+        constructor(config)
+    }
+
+    /// Creates the backend named by `models.default`, falling back to `DEFAULT_MODEL`.
+    pub fn from_config(
+        &self,
+        config: &CognitionConfig,
+    ) -> Result<Box<dyn LargeLanguageModel>, ModelError> {
+        let name = config.models.default.as_deref().unwrap_or(DEFAULT_MODEL);
+        self.create(name, config)
+    }
+}
+
+impl Default for ModelRegistry {
+    /// Creates a registry with the built-in backends.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register_model::<chat_completions::ChatCompletions>("chat_completions");
+        registry.register_model::<davinci003::Davinci003>("davinci003");
+        #[cfg(feature = "local-llama")]
+        registry.register_model::<local_llama::LocalLlama>("local_llama");
+        registry.register_model::<mock::Mock>("mock");
+        registry.register_model::<textgen::Textgen>("textgen");
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn params(stop: &[&str]) -> GenerationParams {
+        GenerationParams {
+            max_tokens: 16,
+            temperature: 0.0,
+            stop: stop.iter().map(|stop| stop.to_string()).collect(),
+        }
+    }
+
+    // Deltas of the truncated stream, with an error as "!"
+    fn truncated(stop: &[&str], deltas: Vec<Result<&str, &str>>) -> Vec<String> {
+        let deltas: Vec<_> = deltas
+            .into_iter()
+            .map(|delta| delta.map(str::to_owned).map_err(ModelError::new))
+            .collect();
+        block_on(
+            params(stop)
+                .truncate_stream(stream::iter(deltas).boxed())
+                .map(|delta| delta.unwrap_or_else(|_| "!".into()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn truncates_at_the_first_stop_sequence() {
+        let mut text = "Buy\nAsk###".to_owned();
+        params(&["###", "\n"]).truncate(&mut text);
+        assert_eq!(text, "Buy");
+        let mut text = "Buy".to_owned();
+        params(&[""]).truncate(&mut text);
+        assert_eq!(text, "Buy");
+    }
+
+    #[test]
+    fn passes_streams_without_stop_sequences_through() {
+        assert_eq!(truncated(&[], vec![Ok("a\n"), Ok("b")]), ["a\n", "b"]);
+        assert_eq!(truncated(&["\n"], vec![Ok("ab"), Ok("c")]), ["ab", "c"]);
+    }
+
+    #[test]
+    fn ends_streams_at_a_stop_sequence_split_across_deltas() {
+        let deltas = vec![Ok("Buy #"), Ok("#"), Ok("# more"), Ok("ignored")];
+        assert_eq!(truncated(&["###"], deltas), ["Buy "]);
+        assert_eq!(truncated(&["###"], vec![Ok("###"), Ok("x")]).len(), 0);
+    }
+
+    #[test]
+    fn releases_text_that_turns_out_not_to_stop() {
+        let deltas = vec![Ok("a#"), Ok("#b"), Ok("c")];
+        assert_eq!(truncated(&["###"], deltas).concat(), "a##bc");
+        assert_eq!(truncated(&["###"], vec![Ok("a##")]).concat(), "a##");
+    }
+
+    #[test]
+    fn ends_streams_at_an_error() {
+        let deltas = vec![Ok("a"), Err("failed"), Ok("b")];
+        assert_eq!(truncated(&["###"], deltas), ["a", "!"]);
+    }
 }
@@ -0,0 +1,181 @@
+use super::{ModelError, STREAM_DONE};
+use futures::{
+    future,
+    stream::{self, BoxStream, Stream, StreamExt, TryStreamExt},
+};
+use reqwest::Response;
+use serde::Deserialize;
+use std::fmt::Display;
+
+/// Reads a response body as server-sent events, yielding the data of each event. Fields
+/// other than `data` and comments are ignored.
+pub(crate) fn events(response: Response) -> BoxStream<'static, Result<String, ModelError>> {
+    parse(response.bytes_stream())
+}
+
+/// Reads a streamed OpenAI-style completion, yielding the text of the first choice of each
+/// chunk until the `[DONE]` event.
+pub(crate) fn completions(response: Response) -> BoxStream<'static, Result<String, ModelError>> {
+    completion_deltas(events(response))
+}
+
+// Chunk of a streamed completion
+#[derive(Deserialize)]
+struct CompletionChunk {
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+fn completion_deltas(
+    events: BoxStream<'static, Result<String, ModelError>>,
+) -> BoxStream<'static, Result<String, ModelError>> {
+    events
+        .try_take_while(|data| future::ready(Ok(data != STREAM_DONE)))
+        .and_then(|data| {
+            future::ready(
+                serde_json::from_str::<CompletionChunk>(&data)
+                    .map(|chunk| {
+                        let choice = chunk.choices.into_iter().next();
+                        choice.and_then(|choice| choice.text).unwrap_or_default()
+                    })
+                    .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e))),
+            )
+        })
+        .boxed()
+}
+
+// Splits a stream of body chunks into the data of its events
+fn parse<B, E>(
+    chunks: impl Stream<Item = Result<B, E>> + Send + 'static,
+) -> BoxStream<'static, Result<String, ModelError>>
+where
+    B: AsRef<[u8]> + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let state = (chunks.boxed(), Vec::new(), Vec::new(), false);
+    stream::unfold(
+        state,
+        |(mut bytes, mut buffer, mut data, mut done)| async move {
+            loop {
+                if let Some(newline) = buffer.iter().position(|byte| *byte == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=newline).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches(['\n', '\r']);
+                    // A blank line ends the event
+                    if line.is_empty() && !data.is_empty() {
+                        let event = data.join("\n");
+                        data.clear();
+                        return Some((Ok(event), (bytes, buffer, data, done)));
+                    }
+                    if let Some(value) = line.strip_prefix("data:") {
+                        data.push(value.strip_prefix(' ').unwrap_or(value).to_owned());
+                    }
+                    continue;
+                }
+                if done {
+                    if data.is_empty() {
+                        return None;
+                    }
+                    let event = data.join("\n");
+                    data.clear();
+                    return Some((Ok(event), (bytes, buffer, data, done)));
+                }
+                match bytes.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                    Some(Err(err)) => {
+                        let err = ModelError::new(&format!("Stream error: {}", err));
+                        return Some((Err(err), (bytes, buffer, data, true)));
+                    }
+                    None => {
+                        // Terminate a last line without a newline
+                        if !buffer.is_empty() {
+                            buffer.push(b'\n');
+                        }
+                        done = true;
+                    }
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn events_of(chunks: &[&'static str]) -> Vec<Result<String, String>> {
+        let chunks: Vec<Result<_, String>> = chunks.iter().copied().map(Ok).collect();
+        let chunks = stream::iter(chunks);
+        block_on(
+            parse(chunks)
+                .map(|event| event.map_err(|err| err.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn yields_the_data_of_each_event() {
+        let events = events_of(&["data: one\n\n", "data: two\r\n\r\n"]);
+        assert_eq!(events, [Ok("one".into()), Ok("two".into())]);
+    }
+
+    #[test]
+    fn joins_events_split_across_chunks() {
+        let events = events_of(&["da", "ta: {\"a\":", " 1}\n", "\ndata:x\n\n"]);
+        assert_eq!(events, [Ok("{\"a\": 1}".into()), Ok("x".into())]);
+    }
+
+    #[test]
+    fn joins_data_lines_and_skips_other_fields() {
+        let events = events_of(&[": comment\nevent: token\nid: 1\ndata: a\ndata: b\n\n\n"]);
+        assert_eq!(events, [Ok("a\nb".into())]);
+    }
+
+    #[test]
+    fn ends_a_last_event_without_a_blank_line() {
+        assert_eq!(events_of(&["data: last"]), [Ok("last".into())]);
+        assert!(events_of(&[]).is_empty());
+    }
+
+    #[test]
+    fn reports_stream_errors() {
+        let chunks = stream::iter(vec![Ok("data: a\n\n"), Err("reset")]);
+        let events: Vec<_> = block_on(parse(chunks).collect());
+        assert_eq!(events.len(), 2);
+        assert!(events[1].is_err());
+    }
+
+    #[test]
+    fn yields_completion_text_until_done() {
+        let chunks: Vec<Result<_, String>> =
+            vec![Ok("data: {\"choices\":[{\"text\":\"Hi\"}]}\n\n\
+            data: {\"choices\":[{}]}\n\ndata: {\"choices\":[]}\n\n\
+            data: {\"choices\":[{\"text\":\" there\"}]}\n\ndata: [DONE]\n\n\
+            data: {\"choices\":[{\"text\":\"late\"}]}\n\n")];
+        let deltas: Vec<_> = block_on(
+            completion_deltas(parse(stream::iter(chunks)))
+                .map(|delta| delta.map_err(|err| err.to_string()))
+                .collect(),
+        );
+        assert_eq!(
+            deltas,
+            [
+                Ok("Hi".into()),
+                Ok("".into()),
+                Ok("".into()),
+                Ok(" there".into())
+            ]
+        );
+
+        let chunks: Vec<Result<_, String>> = vec![Ok("data: oops\n\n")];
+        let deltas: Vec<_> = block_on(completion_deltas(parse(stream::iter(chunks))).collect());
+        assert!(deltas[0].is_err());
+    }
+}
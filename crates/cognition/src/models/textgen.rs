@@ -1,24 +1,61 @@
-use crate::models::{InferenceResult, LargeLanguageModel, ModelError};
+use crate::{
+    config::CognitionConfig,
+    models::{sse, GenerationParams, InferenceResult, LargeLanguageModel, ModelError, TokenStream},
+};
 use async_trait::async_trait;
+use futures::{future, stream, SinkExt, StreamExt, TryStreamExt};
 use reqwest::{
     header::{HeaderMap, HeaderValue, CONTENT_TYPE},
-    Client, StatusCode,
+    Client, Response,
 };
 use serde::Deserialize;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use tokio_tungstenite::tungstenite::Message;
 
 pub struct Textgen {
     server: String,
+    api: TextgenApi,
+    stream_url: Option<String>,
+    params: TextgenParams,
     client: Client,
 }
 
-// Generation parameters
+/// The `models.textgen` config section.
+#[derive(Deserialize)]
+pub struct TextgenConfig {
+    pub server: String,
+    #[serde(default)]
+    pub api: TextgenApi,
+    /// Websocket streaming API of the legacy text-generation-webui API, e.g.
+    /// `ws://localhost:5005/api/v1/stream`. Without it, streamed responses arrive in one piece.
+    #[serde(default)]
+    pub stream_url: Option<String>,
+    #[serde(default)]
+    pub params: TextgenParams,
+}
+
+/// API implemented by the `textgen` server.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextgenApi {
+    /// text-generation-webui's OpenAI-compatible `/v1/completions`, which also takes its
+    /// named sampling parameters.
+    #[default]
+    Webui,
+    /// text-generation-webui's `/api/v1/generate` and websocket streaming API, which the
+    /// OpenAI-compatible API replaced in late 2023.
+    LegacyWebui,
+    /// Hugging Face text-generation-inference's `/generate` and `/generate_stream`.
+    Tgi,
+}
+
 // Reference: https://huggingface.co/docs/transformers/main_classes/text_generation#transformers.GenerationConfig
-#[derive(Debug, PartialEq, Clone)]
+/// Sampling parameters sent with every request, from `models.textgen.params`. The maximum
+/// number of new tokens, the temperature and the stop sequences come from `GenerationParams`.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct TextgenParams {
-    pub max_new_tokens: usize,
     pub do_sample: bool,
-    pub temperature: f32,
     pub top_p: f32,
     pub typical_p: f32,
     pub repetition_penalty: f32,
@@ -32,62 +69,10 @@ pub struct TextgenParams {
     pub early_stopping: bool,
 }
 
-impl TextgenParams {
-    fn to_json_data(&self, prompt: &str) -> Value {
-        json!({
-            "data": [
-                prompt,
-                self.max_new_tokens,
-                self.do_sample,
-                self.temperature,
-                self.top_p,
-                self.typical_p,
-                self.repetition_penalty,
-                self.encoder_repetition_penalty,
-                self.top_k,
-                self.min_length,
-                self.no_repeat_ngram_size,
-                self.num_beams,
-                self.penalty_alpha,
-                self.length_penalty,
-                self.early_stopping,
-            ]
-        })
-    }
-}
-
-#[derive(Debug, Deserialize)]
-pub struct TextgenResponse {
-    data: Vec<Option<String>>,
-    pub is_generating: bool,
-    pub duration: f64,
-    pub average_duration: f64,
-}
-
-#[async_trait(?Send)]
-impl LargeLanguageModel for Textgen {
-    fn new(_config: &String) -> Result<Self, ModelError> {
-        Ok(Textgen {
-            server: std::env::var("TEXTGEN_SERVER").map_err(|e| {
-                ModelError::new(&format!("Cannot get TEXTGEN_SERVER from env var: {}", e))
-            })?,
-            client: Client::new(),
-        })
-    }
-
-    async fn generate(
-        &self,
-        prompt: &str,
-        max_length: usize,
-        temperature: f32,
-    ) -> Result<InferenceResult, ModelError> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-
-        let params = TextgenParams {
-            max_new_tokens: max_length,
+impl Default for TextgenParams {
+    fn default() -> Self {
+        Self {
             do_sample: true,
-            temperature,
             top_p: 0.9,
             typical_p: 1.0,
             repetition_penalty: 1.05,
@@ -99,43 +84,363 @@ impl LargeLanguageModel for Textgen {
             penalty_alpha: 0.0,
             length_penalty: 1.0,
             early_stopping: true,
-        };
+        }
+    }
+}
+
+// TGI accepts at most four stop sequences by default
+const TGI_MAX_STOP_SEQUENCES: usize = 4;
+
+impl TextgenParams {
+    // Named sampling parameters of text-generation-webui
+    fn to_webui_params(&self) -> Map<String, Value> {
+        let mut params = Map::new();
+        params.insert("do_sample".into(), json!(self.do_sample));
+        params.insert("top_p".into(), json!(self.top_p));
+        params.insert("typical_p".into(), json!(self.typical_p));
+        params.insert("repetition_penalty".into(), json!(self.repetition_penalty));
+        params.insert(
+            "encoder_repetition_penalty".into(),
+            json!(self.encoder_repetition_penalty),
+        );
+        params.insert("top_k".into(), json!(self.top_k));
+        params.insert("min_length".into(), json!(self.min_length));
+        params.insert(
+            "no_repeat_ngram_size".into(),
+            json!(self.no_repeat_ngram_size),
+        );
+        params.insert("num_beams".into(), json!(self.num_beams));
+        params.insert("penalty_alpha".into(), json!(self.penalty_alpha));
+        params.insert("length_penalty".into(), json!(self.length_penalty));
+        params.insert("early_stopping".into(), json!(self.early_stopping));
+        params
+    }
+
+    // Request of text-generation-webui's OpenAI-compatible API
+    fn to_webui_request(&self, prompt: &str, params: &GenerationParams, stream: bool) -> Value {
+        let mut request = self.to_webui_params();
+        request.insert("prompt".into(), json!(prompt));
+        request.insert("max_tokens".into(), json!(params.max_tokens));
+        request.insert("temperature".into(), json!(params.temperature));
+        request.insert("stop".into(), json!(params.stop));
+        request.insert("stream".into(), json!(stream));
+        Value::Object(request)
+    }
+
+    // Request of the legacy text-generation-webui API, for both the blocking and the
+    // streaming API
+    fn to_legacy_webui_request(&self, prompt: &str, params: &GenerationParams) -> Value {
+        let mut request = self.to_webui_params();
+        request.insert("prompt".into(), json!(prompt));
+        request.insert("max_new_tokens".into(), json!(params.max_tokens));
+        request.insert("temperature".into(), json!(params.temperature));
+        request.insert("stopping_strings".into(), json!(params.stop));
+        Value::Object(request)
+    }
+
+    // Request of TGI, which rejects the values that disable a parameter, so those are left out
+    fn to_tgi_request(&self, prompt: &str, params: &GenerationParams) -> Value {
+        let sample = self.do_sample && params.temperature > 0.0;
+        let mut parameters = Map::new();
+        parameters.insert("max_new_tokens".into(), json!(params.max_tokens));
+        parameters.insert("do_sample".into(), json!(sample));
+        if sample {
+            parameters.insert("temperature".into(), json!(params.temperature));
+        }
+        if self.top_p > 0.0 && self.top_p < 1.0 {
+            parameters.insert("top_p".into(), json!(self.top_p));
+        }
+        if self.typical_p > 0.0 && self.typical_p < 1.0 {
+            parameters.insert("typical_p".into(), json!(self.typical_p));
+        }
+        if self.top_k > 0 {
+            parameters.insert("top_k".into(), json!(self.top_k));
+        }
+        if self.repetition_penalty > 0.0 {
+            parameters.insert("repetition_penalty".into(), json!(self.repetition_penalty));
+        }
+        parameters.insert(
+            "stop".into(),
+            json!(params.stop_for_api(TGI_MAX_STOP_SEQUENCES)),
+        );
+        json!({
+            "inputs": prompt,
+            "parameters": parameters,
+        })
+    }
+}
+
+// Response of the OpenAI-compatible API
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<WebuiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyWebuiResponse {
+    results: Vec<WebuiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebuiResult {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TgiResponse {
+    #[serde(default)]
+    generated_text: Option<String>,
+}
+
+// Message of the legacy webui streaming API
+#[derive(Debug, Deserialize)]
+struct TextgenStreamEvent {
+    event: String,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+// Event that ends a streamed response
+const STREAM_END: &str = "stream_end";
+
+// Event of TGI's `/generate_stream`; errors during generation come as events as well
+#[derive(Debug, Deserialize)]
+struct TgiStreamEvent {
+    #[serde(default)]
+    token: Option<TgiToken>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TgiToken {
+    text: String,
+    // Special tokens, such as the end of sequence, are not part of the text
+    #[serde(default)]
+    special: bool,
+}
+
+fn no_data() -> ModelError {
+    ModelError::new("No data found in textgen response")
+}
+
+impl Textgen {
+    async fn post(&self, path: &str, body: &Value) -> Result<Response, ModelError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
-        let request_body = params.to_json_data(prompt);
         let response = self
             .client
-            .post(format!("{}/run/textgen", self.server))
+            .post(format!("{}{}", self.server.trim_end_matches('/'), path))
             .headers(headers)
-            .json(&request_body)
+            .json(body)
             .send()
             .await
             .map_err(|e| ModelError::new(&format!("HTTP request error: {}", e)))?;
 
         let status = response.status();
-
-        if status != StatusCode::OK {
+        if !status.is_success() {
             let error_body = response
                 .text()
                 .await
                 .unwrap_or_else(|_| String::from("No error details"));
-            println!("Error body: {}", error_body); // Print the response body with the error message
             return Err(ModelError::new(&format!(
                 "Error {}: {}",
                 status, error_body
             )));
         }
+        Ok(response)
+    }
+}
 
-        let response_data = response
-            .json::<TextgenResponse>()
-            .await
-            .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e)))?;
-        let result = InferenceResult {
-            text: response_data.data[0]
-                .clone()
-                .unwrap_or_else(|| String::from("No data found")),
-            probabilities: vec![], // You may want to calculate probabilities based on your requirements
+#[async_trait]
+impl LargeLanguageModel for Textgen {
+    fn new(config: &CognitionConfig) -> Result<Self, ModelError> {
+        let TextgenConfig {
+            server,
+            api,
+            stream_url,
+            params,
+        } = config.models.backend("textgen")?;
+        Ok(Textgen {
+            server,
+            api,
+            stream_url,
+            params,
+            client: Client::new(),
+        })
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        max_length: usize,
+        temperature: f32,
+    ) -> Result<InferenceResult, ModelError> {
+        let params = GenerationParams {
+            max_tokens: max_length,
+            temperature,
+            stop: vec![],
         };
+        self.generate_with(prompt, &params).await
+    }
+
+    async fn generate_with(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceResult, ModelError> {
+        let parse_error =
+            |e: reqwest::Error| ModelError::new(&format!("JSON parsing error: {}", e));
+        let text = match self.api {
+            TextgenApi::Webui => {
+                let request_body = self.params.to_webui_request(prompt, params, false);
+                self.post("/v1/completions", &request_body)
+                    .await?
+                    .json::<CompletionResponse>()
+                    .await
+                    .map_err(parse_error)?
+                    .choices
+                    .into_iter()
+                    .next()
+                    .and_then(|result| result.text)
+            }
+            TextgenApi::LegacyWebui => {
+                let request_body = self.params.to_legacy_webui_request(prompt, params);
+                self.post("/api/v1/generate", &request_body)
+                    .await?
+                    .json::<LegacyWebuiResponse>()
+                    .await
+                    .map_err(parse_error)?
+                    .results
+                    .into_iter()
+                    .next()
+                    .and_then(|result| result.text)
+            }
+            TextgenApi::Tgi => {
+                let request_body = self.params.to_tgi_request(prompt, params);
+                self.post("/generate", &request_body)
+                    .await?
+                    .json::<TgiResponse>()
+                    .await
+                    .map_err(parse_error)?
+                    .generated_text
+            }
+        };
+
+        // An absent or empty text is an error in every API
+        let mut result = InferenceResult {
+            text: text.filter(|text| !text.is_empty()).ok_or_else(no_data)?,
+            probabilities: vec![],
+        };
+        params.truncate(&mut result.text);
 
         Ok(result)
     }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<TokenStream, ModelError> {
+        if self.api == TextgenApi::Webui {
+            let request_body = self.params.to_webui_request(prompt, params, true);
+            let response = self.post("/v1/completions", &request_body).await?;
+            return Ok(params.truncate_stream(sse::completions(response)));
+        }
+
+        if self.api == TextgenApi::Tgi {
+            let request_body = self.params.to_tgi_request(prompt, params);
+            let response = self.post("/generate_stream", &request_body).await?;
+            let deltas = sse::events(response)
+                .and_then(|data| {
+                    future::ready(match serde_json::from_str::<TgiStreamEvent>(&data) {
+                        Ok(TgiStreamEvent {
+                            error: Some(error), ..
+                        }) => Err(ModelError::new(&format!("Generation error: {}", error))),
+                        Ok(TgiStreamEvent { token, .. }) => {
+                            Ok(token.filter(|token| !token.special).map(|token| token.text))
+                        }
+                        Err(e) => Err(ModelError::new(&format!("JSON parsing error: {}", e))),
+                    })
+                })
+                .try_filter_map(future::ok)
+                .boxed();
+            return Ok(params.truncate_stream(deltas));
+        }
+
+        let stream_url = match &self.stream_url {
+            Some(stream_url) => stream_url,
+            None => {
+                let result = self.generate_with(prompt, params).await?;
+                return Ok(stream::once(async move { Ok(result.text) }).boxed());
+            }
+        };
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(stream_url.as_str())
+            .await
+            .map_err(|e| ModelError::new(&format!("Websocket error: {}", e)))?;
+        let request = self.params.to_legacy_webui_request(prompt, params);
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .map_err(|e| ModelError::new(&format!("Websocket error: {}", e)))?;
+
+        let deltas = socket
+            .map_err(|e| ModelError::new(&format!("Websocket error: {}", e)))
+            .try_filter_map(|message| {
+                future::ready(match message {
+                    Message::Text(text) => serde_json::from_str::<TextgenStreamEvent>(&text)
+                        .map(Some)
+                        .map_err(|e| ModelError::new(&format!("JSON parsing error: {}", e))),
+                    _ => Ok(None),
+                })
+            })
+            .try_take_while(|event| future::ready(Ok(event.event != STREAM_END)))
+            .try_filter_map(|event| future::ready(Ok(event.text)))
+            .boxed();
+        Ok(params.truncate_stream(deltas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> GenerationParams {
+        GenerationParams {
+            max_tokens: 16,
+            temperature: 0.5,
+            stop: vec!["\n".into()],
+        }
+    }
+
+    #[test]
+    fn webui_request_uses_the_openai_compatible_names() {
+        let request = TextgenParams::default().to_webui_request("prompt", &params(), true);
+        assert_eq!(request["prompt"], "prompt");
+        assert_eq!(request["max_tokens"], 16);
+        assert_eq!(request["stop"], json!(["\n"]));
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["top_p"], json!(0.9_f32));
+        assert!(request.get("max_new_tokens").is_none());
+    }
+
+    #[test]
+    fn legacy_webui_request_uses_the_named_parameters() {
+        let request = TextgenParams::default().to_legacy_webui_request("prompt", &params());
+        assert_eq!(request["max_new_tokens"], 16);
+        assert_eq!(request["stopping_strings"], json!(["\n"]));
+        assert!(request.get("stream").is_none());
+    }
+
+    #[test]
+    fn completion_responses_parse_with_and_without_text() {
+        let chunk: CompletionResponse =
+            serde_json::from_str(r#"{"choices":[{"text":"Hi","index":0}]}"#).unwrap();
+        assert_eq!(chunk.choices[0].text.as_deref(), Some("Hi"));
+        let chunk: CompletionResponse = serde_json::from_str(r#"{"choices":[{}]}"#).unwrap();
+        assert_eq!(chunk.choices[0].text, None);
+    }
 }
@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// A JSON-Schema-like description of an object: typed properties, some of them required.
+///
+/// ```yaml
+/// type: object
+/// properties:
+///   input:
+///     type: string
+///     description: The question to ask
+/// required: [input]
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Parameters {
+    #[serde(default)]
+    pub properties: BTreeMap<String, Parameter>,
+    #[serde(default)]
+    pub required: Vec<String>,
+}
+
+/// A single property of `Parameters`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Parameter {
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Allowed values, if the property is an enumeration.
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<Value>>,
+}
+
+/// JSON type of a `Parameter`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl ParameterType {
+    /// Whether the value has this type.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            ParameterType::String => value.is_string(),
+            ParameterType::Number => value.is_number(),
+            ParameterType::Integer => value.is_i64() || value.is_u64(),
+            ParameterType::Boolean => value.is_boolean(),
+            ParameterType::Array => value.is_array(),
+            ParameterType::Object => value.is_object(),
+        }
+    }
+}
+
+impl Display for ParameterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ParameterType::String => "string",
+            ParameterType::Number => "number",
+            ParameterType::Integer => "integer",
+            ParameterType::Boolean => "boolean",
+            ParameterType::Array => "array",
+            ParameterType::Object => "object",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl Parameters {
+    /// The parameters as a JSON schema, as shown to the model.
+    pub fn schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": self.properties,
+            "required": self.required,
+        })
+    }
+
+    /// Checks a value against the parameters, returning one message per problem. Properties
+    /// that are not described are ignored.
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<String>> {
+        let object = match value {
+            Value::Object(object) => object,
+            _ => return Err(vec!["Arguments must be a JSON object".into()]),
+        };
+        let mut errors = vec![];
+        for name in &self.required {
+            if object.get(name).is_none_or(Value::is_null) {
+                errors.push(format!("Missing required argument '{}'", name));
+            }
+        }
+        for (name, value) in object {
+            let parameter = match self.properties.get(name) {
+                Some(parameter) if !value.is_null() => parameter,
+                _ => continue,
+            };
+            if !parameter.kind.matches(value) {
+                errors.push(format!(
+                    "Argument '{}' must be of type {}, got {}",
+                    name, parameter.kind, value
+                ));
+            } else if let Some(allowed) = &parameter.allowed {
+                if !allowed.contains(value) {
+                    errors.push(format!(
+                        "Argument '{}' must be one of {}, got {}",
+                        name,
+                        Value::Array(allowed.clone()),
+                        value
+                    ));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Parses the first JSON object in a model response.
+pub fn parse_object(response: &str) -> Result<Value, String> {
+    let start = response.find('{');
+    let end = response.rfind('}');
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&response[start..=end])
+            .map_err(|err| format!("Response is not valid JSON: {}", err)),
+        _ => Err("Response does not contain a JSON object".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parameters() -> Parameters {
+        serde_yaml::from_str(
+            r#"
+properties:
+  city: {type: string}
+  days: {type: integer}
+  units: {type: string, enum: [metric, imperial]}
+required: [city]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_valid_arguments() {
+        let parameters = parameters();
+        assert_eq!(parameters.validate(&json!({"city": "Paris"})), Ok(()));
+        assert_eq!(
+            parameters.validate(&json!({"city": "Paris", "days": 3, "units": "metric"})),
+            Ok(())
+        );
+        // Properties that are not described, and null optional ones, are ignored
+        assert_eq!(
+            parameters.validate(&json!({"city": "Paris", "days": null, "extra": 1})),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reports_a_missing_required_argument() {
+        let parameters = parameters();
+        let missing = Err(vec!["Missing required argument 'city'".to_string()]);
+        assert_eq!(parameters.validate(&json!({})), missing);
+        assert_eq!(parameters.validate(&json!({"city": null})), missing);
+        assert_eq!(
+            parameters.validate(&json!("Paris")),
+            Err(vec!["Arguments must be a JSON object".to_string()])
+        );
+    }
+
+    #[test]
+    fn reports_a_wrong_type() {
+        assert_eq!(
+            parameters().validate(&json!({"city": "Paris", "days": 1.5})),
+            Err(vec![
+                "Argument 'days' must be of type integer, got 1.5".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_a_value_outside_the_enum() {
+        assert_eq!(
+            parameters().validate(&json!({"units": "kelvin"})),
+            Err(vec![
+                "Missing required argument 'city'".to_string(),
+                r#"Argument 'units' must be one of ["metric","imperial"], got "kelvin""#
+                    .to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_the_first_object_in_a_response() {
+        assert_eq!(
+            parse_object("Sure: {\"city\": \"Paris\"} done"),
+            Ok(json!({"city": "Paris"}))
+        );
+        assert_eq!(
+            parse_object("no object").unwrap_err(),
+            "Response does not contain a JSON object"
+        );
+        assert!(parse_object("{city: Paris}")
+            .unwrap_err()
+            .starts_with("Response is not valid JSON"));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{engine::Prediction, templates::context, validation::START_ID, ToolResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Mutable state of a single conversation.
+///
+/// The decision tree, prompt template, model and tools live in a shared `DecisionState`,
+/// so a session can be saved, restored or moved to another process on its own.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Session {
+    /// Id of the current decision node.
+    pub current_id: String,
+    /// Conversation history as it is passed to the decision prompt.
+    pub history: String,
+    /// Predictions made by the last decision.
+    #[serde(default)]
+    pub predictions: Vec<Prediction>,
+    /// Conversation variables.
+    #[serde(default)]
+    pub variables: HashMap<String, Value>,
+    /// Latest response of each tool that has run, by tool id.
+    #[serde(default)]
+    pub tool_responses: HashMap<String, ToolResponse>,
+    /// Nodes to return to from the sub-trees the conversation is in, innermost last.
+    #[serde(default)]
+    pub call_stack: Vec<String>,
+}
+
+impl Session {
+    /// Creates a session at the `start` node with an empty history.
+    pub fn new() -> Self {
+        Self {
+            current_id: START_ID.to_string(),
+            history: String::new(),
+            predictions: vec![],
+            variables: HashMap::new(),
+            tool_responses: HashMap::new(),
+            call_stack: vec![],
+        }
+    }
+
+    /// Values available to node text and the prompt template, e.g.
+    /// `{{tools.wolfram_alpha.response}}` or `{{vars.quantity}}`.
+    pub fn context(&self) -> Value {
+        serde_json::json!({ "tools": self.tool_responses, "vars": self.variables })
+    }
+
+    /// Replaces the placeholders that refer to the session context in a text. A placeholder
+    /// whose value is missing is replaced by an empty string.
+    pub fn render(&self, text: &str) -> String {
+        context::render(text, &self.context())
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,70 @@
+// Template used unless the state is given another one
+pub const DEFAULT_TEMPLATE: &str = r#"---
+
+# This is a conversation between the user and an agent.
+history: |
+  {{history}}
+
+# The agent needs some values from the conversation, for example to use a tool.
+name: {{name}}
+description: {{description}}
+
+# The values to fill in, as a JSON schema.
+parameters: {{parameters}}
+
+# The user's latest response.
+response: {{user_input}}
+{{errors}}
+# Fill in the values from the conversation, as a single JSON object.
+# Use null for values the user has not given.
+arguments: "#;
+
+use crate::Session;
+
+// Prompt used to fill in tool arguments and node slots with the model
+pub struct ArgumentsPromptTemplate(String);
+
+impl ArgumentsPromptTemplate {
+    pub fn new(content: String) -> Self {
+        Self(content)
+    }
+
+    // Replace the placeholders that refer to the session context, such as tool responses
+    pub fn render(&self, session: &Session) -> Self {
+        Self(session.render(&self.0))
+    }
+
+    // Format the template; errors are the problems with the previous attempt, if any
+    pub fn format(
+        &self,
+        history: &str,
+        name: &str,
+        description: &str,
+        parameters: &str,
+        user_input: &str,
+        errors: &[String],
+    ) -> String {
+        let errors = if errors.is_empty() {
+            String::new()
+        } else {
+            let errors: Vec<String> = errors.iter().map(|err| format!("  - {}", err)).collect();
+            format!(
+                "\n# The previous arguments were rejected.\nerrors:\n{}\n",
+                errors.join("\n")
+            )
+        };
+        self.0
+            .replace("{{history}}", history)
+            .replace("{{name}}", name)
+            .replace("{{description}}", description)
+            .replace("{{parameters}}", parameters)
+            .replace("{{user_input}}", user_input)
+            .replace("{{errors}}", &errors)
+    }
+}
+
+impl Default for ArgumentsPromptTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE.into())
+    }
+}
@@ -0,0 +1,44 @@
+use serde_json::Value;
+
+// Replace `{{a.b.c}}` placeholders whose first segment is a key of the context object. Paths
+// that do not resolve are replaced by an empty string, other placeholders such as
+// `{{history}}` are left as they are.
+pub fn render(text: &str, context: &Value) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find("}}").and_then(|end| {
+            let path = rest[2..end].trim();
+            let root = path.split('.').next()?;
+            context.get(root)?;
+            Some((end, lookup(context, path)))
+        });
+        match value {
+            Some((end, value)) => {
+                result.push_str(&value);
+                rest = &rest[end + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// Strings without quotes, other values as JSON and missing values as an empty string
+fn lookup(context: &Value, path: &str) -> String {
+    let value = path.split('.').try_fold(context, |value, key| match value {
+        Value::Array(values) => values.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    });
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
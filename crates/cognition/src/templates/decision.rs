@@ -1,3 +1,13 @@
+// Placeholders every decision prompt template must contain
+pub const PLACEHOLDERS: [&str; 4] = [
+    "{{history}}",
+    "{{decision_prompt}}",
+    "{{choices}}",
+    "{{user_input}}",
+];
+
+use crate::Session;
+
 // YAML prompt_decision template object
 pub struct DecisionPromptTemplate(String);
 
@@ -6,6 +16,19 @@ impl DecisionPromptTemplate {
         Self(content)
     }
 
+    // Placeholders missing from the template
+    pub fn missing_placeholders(&self) -> Vec<&'static str> {
+        PLACEHOLDERS
+            .into_iter()
+            .filter(|placeholder| !self.0.contains(placeholder))
+            .collect()
+    }
+
+    // Replace the placeholders that refer to the session context, such as tool responses
+    pub fn render(&self, session: &Session) -> Self {
+        Self(session.render(&self.0))
+    }
+
     // Format the decision prompt template with the given parameters
     pub fn format(
         &self,
@@ -1 +1,3 @@
-pub mod decision;
\ No newline at end of file
+pub mod arguments;
+pub mod context;
+pub mod decision;
@@ -0,0 +1,443 @@
+use super::*;
+use crate::config::interpolate;
+use jsonpath_lib::Compiled;
+use regex::Regex;
+use reqwest::Method;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// An HTTP tool as described in YAML, e.g. `wolfram_tool.yaml`.
+///
+/// The URL, query, header and body templates may contain `{{<argument>}}` placeholders and
+/// `${...}` references (see `config::interpolate`). Without `parameters`, the only argument is
+/// `input`. References are resolved on every request, so a missing variable only fails the tool
+/// when it is run.
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpToolDefinition {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    /// Arguments filled in by the model before the tool is run.
+    #[serde(default)]
+    pub parameters: Option<Parameters>,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(alias = "endpoint")]
+    pub url: String,
+    #[serde(default, alias = "params")]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// A string is sent as is, anything else is sent as JSON.
+    #[serde(default)]
+    pub body: Option<Value>,
+    /// Extracts the tool response from the response body; the whole body is used otherwise.
+    #[serde(default)]
+    pub extract: Option<Extract>,
+}
+
+fn default_method() -> String {
+    "GET".into()
+}
+
+/// How to extract the tool response from a response body.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Extract {
+    /// The first node matched by a JSONPath query; strings are returned without quotes.
+    JsonPath(String),
+    /// The first capture group of a regular expression, or the whole match if it has none.
+    Regex(String),
+}
+
+enum Extractor {
+    JsonPath(Compiled),
+    Regex(Regex),
+}
+
+/// A `Tool` that sends a templated HTTP request.
+pub struct HttpTool {
+    definition: HttpToolDefinition,
+    method: Method,
+    extractor: Option<Extractor>,
+}
+
+impl HttpTool {
+    pub fn new(definition: HttpToolDefinition) -> Result<Self, CognitionError> {
+        let invalid = |key: &str, err: &dyn std::fmt::Display| {
+            CognitionError(format!(
+                "Invalid tool '{}' key '{}': {}",
+                definition.id, key, err
+            ))
+        };
+        let method = Method::from_bytes(definition.method.to_uppercase().as_bytes())
+            .map_err(|err| invalid("method", &err))?;
+        let extractor = match &definition.extract {
+            Some(Extract::JsonPath(path)) => Some(Extractor::JsonPath(
+                Compiled::compile(path).map_err(|err| invalid("extract.json_path", &err))?,
+            )),
+            Some(Extract::Regex(pattern)) => Some(Extractor::Regex(
+                Regex::new(pattern).map_err(|err| invalid("extract.regex", &err))?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            definition,
+            method,
+            extractor,
+        })
+    }
+
+    /// Parses a tool definition from YAML.
+    pub fn from_yaml(yaml: &str) -> Result<Self, CognitionError> {
+        let definition = serde_yaml::from_str(yaml)
+            .map_err(|err| CognitionError(format!("Unable to parse tool definition: {}", err)))?;
+        Self::new(definition)
+    }
+
+    /// Reads a tool definition from a YAML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CognitionError> {
+        let path = path.as_ref();
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|err| CognitionError(format!("Unable to read {}: {}", path.display(), err)))?;
+        Self::from_yaml(&yaml)
+            .map_err(|err| CognitionError(format!("{}: {}", path.display(), err.0)))
+    }
+
+    pub fn definition(&self) -> &HttpToolDefinition {
+        &self.definition
+    }
+
+    // Resolve references first, so that the arguments cannot expand them
+    fn render(
+        &self,
+        key: &str,
+        template: &str,
+        args: &HashMap<&str, String>,
+    ) -> Result<String, CognitionError> {
+        let text = interpolate(template).map_err(|message| {
+            CognitionError(format!(
+                "Unable to resolve tool '{}' key '{}': {}",
+                self.definition.id, key, message
+            ))
+        })?;
+        Ok(substitute(&text, args))
+    }
+
+    fn render_value(
+        &self,
+        key: &str,
+        value: &Value,
+        input: &HashMap<&str, String>,
+    ) -> Result<Value, CognitionError> {
+        Ok(match value {
+            Value::String(text) => Value::String(self.render(key, text, input)?),
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| self.render_value(&format!("{}[{}]", key, i), value, input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(object) => Value::Object(
+                object
+                    .iter()
+                    .map(|(name, value)| {
+                        let value =
+                            self.render_value(&format!("{}.{}", key, name), value, input)?;
+                        Ok((name.clone(), value))
+                    })
+                    .collect::<Result<_, CognitionError>>()?,
+            ),
+            other => other.clone(),
+        })
+    }
+
+    fn extract(&self, body: String) -> Result<String, CognitionError> {
+        let no_match = || {
+            CognitionError(format!(
+                "Tool '{}' response did not match the extract pattern: {}",
+                self.definition.id, body
+            ))
+        };
+        match &self.extractor {
+            Some(Extractor::JsonPath(path)) => {
+                let json: Value = serde_json::from_str(&body).map_err(|err| {
+                    CognitionError(format!(
+                        "Tool '{}' response is not JSON: {}",
+                        self.definition.id, err
+                    ))
+                })?;
+                let nodes = path.select(&json).map_err(|err| {
+                    CognitionError(format!(
+                        "Tool '{}' JSONPath query failed: {}",
+                        self.definition.id, err
+                    ))
+                })?;
+                match nodes.first() {
+                    Some(Value::String(text)) => Ok(text.clone()),
+                    Some(value) => Ok(value.to_string()),
+                    None => Err(no_match()),
+                }
+            }
+            Some(Extractor::Regex(regex)) => {
+                let captures = regex.captures(&body).ok_or_else(no_match)?;
+                let capture = captures.get(1).or_else(|| captures.get(0));
+                Ok(capture.map(|m| m.as_str().to_string()).unwrap_or_default())
+            }
+            None => Ok(body),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for HttpTool {
+    fn id(&self) -> &String {
+        &self.definition.id
+    }
+
+    fn name(&self) -> &String {
+        &self.definition.name
+    }
+
+    fn description(&self) -> &String {
+        &self.definition.description
+    }
+
+    fn parameters(&self) -> Option<&Parameters> {
+        self.definition.parameters.as_ref()
+    }
+
+    async fn run(&self, args: &Value) -> Result<Option<ToolResponse>, CognitionError> {
+        let definition = &self.definition;
+        let empty = Map::new();
+        let args = args.as_object().unwrap_or(&empty);
+        let input: HashMap<&str, String> = args
+            .iter()
+            .map(|(name, value)| (name.as_str(), argument_text(value)))
+            .collect();
+
+        // Arguments are URL encoded in the URL itself, the query is encoded by reqwest
+        let encoded: HashMap<&str, String> = input
+            .iter()
+            .map(|(name, value)| {
+                let value = url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+                (*name, value)
+            })
+            .collect();
+        let url = self.render("url", &definition.url, &encoded)?;
+        let url = Url::parse(&url).map_err(|err| {
+            CognitionError(format!("Invalid tool '{}' URL: {}", definition.id, err))
+        })?;
+
+        let mut query = Vec::new();
+        for (name, template) in &definition.query {
+            let key = format!("query.{}", name);
+            query.push((name, self.render(&key, template, &input)?));
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, template) in &definition.headers {
+            let key = format!("headers.{}", name);
+            let value = self.render(&key, template, &input)?;
+            let invalid = |err: &dyn std::fmt::Display| {
+                CognitionError(format!(
+                    "Invalid tool '{}' key '{}': {}",
+                    definition.id, key, err
+                ))
+            };
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|err| invalid(&err))?,
+                value.parse().map_err(|err| invalid(&err))?,
+            );
+        }
+
+        let client = reqwest::Client::new();
+        let mut request = client
+            .request(self.method.clone(), url)
+            .query(&query)
+            .headers(headers);
+        match &definition.body {
+            Some(Value::String(text)) => request = request.body(self.render("body", text, &input)?),
+            Some(body) => request = request.json(&self.render_value("body", body, &input)?),
+            None => {}
+        }
+
+        // Send request to AI tool
+        let response = request.send().await.map_err(|err| {
+            CognitionError(format!(
+                "Failed to send request to tool: {}",
+                err.without_url()
+            ))
+        })?;
+        let status = response.status();
+        let response = response.text().await.map_err(|err| {
+            CognitionError(format!(
+                "Failed to get response text: {}",
+                err.without_url()
+            ))
+        })?;
+        debug!("{}: {} {}", definition.id, status, response);
+        if !status.is_success() {
+            return Err(CognitionError(format!(
+                "Tool '{}' request failed with {}: {}",
+                definition.id, status, response
+            )));
+        }
+
+        Ok(Some(ToolResponse {
+            id: definition.id.clone(),
+            response: self.extract(response)?,
+        }))
+    }
+}
+
+// Strings are substituted without quotes, other values as JSON
+fn argument_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Replace `{{name}}` placeholders in one pass, leaving unknown ones as they are
+fn substitute(template: &str, args: &HashMap<&str, String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find("}}")
+            .and_then(|end| Some((end, args.get(rest[2..end].trim())?)));
+        match value {
+            Some((end, value)) => {
+                result.push_str(value);
+                rest = &rest[end + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(extract: &str) -> HttpTool {
+        HttpTool::from_yaml(&format!(
+            "{{id: test, name: Test, description: Test, url: 'http://localhost', {}}}",
+            extract
+        ))
+        .unwrap()
+    }
+
+    fn arguments<'a>(pairs: &[(&'a str, &str)]) -> HashMap<&'a str, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn substitutes_known_placeholders_only() {
+        let args = arguments(&[("city", "Paris"), ("units", "{{city}}")]);
+        assert_eq!(
+            substitute("{{city}}, {{ units }}: {{unknown}} {{", &args),
+            "Paris, {{city}}: {{unknown}} {{"
+        );
+        assert_eq!(argument_text(&json!("text")), "text");
+        assert_eq!(argument_text(&json!({"a": 1})), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn resolves_references_before_the_arguments() {
+        std::env::set_var("COGNITION_HTTP_TEST_KEY", "secret");
+        let tool = tool("extract: {regex: x}");
+        let args = arguments(&[("input", "${COGNITION_HTTP_TEST_KEY}")]);
+        assert_eq!(
+            tool.render("query.q", "${COGNITION_HTTP_TEST_KEY}: {{input}}", &args)
+                .unwrap(),
+            "secret: ${COGNITION_HTTP_TEST_KEY}"
+        );
+        let err = tool
+            .render("query.q", "${COGNITION_HTTP_TEST_UNSET}", &args)
+            .unwrap_err();
+        assert_eq!(
+            err.0,
+            "Unable to resolve tool 'test' key 'query.q': \
+             Environment variable 'COGNITION_HTTP_TEST_UNSET' is not set"
+        );
+
+        let body = json!({"items": ["{{input}}", 1], "nested": {"key": "{{input}}"}});
+        let input = arguments(&[("input", "a")]);
+        assert_eq!(
+            tool.render_value("body", &body, &input).unwrap(),
+            json!({"items": ["a", 1], "nested": {"key": "a"}})
+        );
+    }
+
+    #[test]
+    fn extracts_with_json_path() {
+        let tool = tool("extract: {json_path: '$.current.summary'}");
+        let body = r#"{"current": {"summary": "Sunny"}}"#;
+        assert_eq!(tool.extract(body.into()).unwrap(), "Sunny");
+        let tool = self::tool("extract: {json_path: '$.current'}");
+        assert_eq!(tool.extract(body.into()).unwrap(), r#"{"summary":"Sunny"}"#);
+        assert!(tool
+            .extract("{}".into())
+            .unwrap_err()
+            .0
+            .contains("did not match"));
+        assert!(tool
+            .extract("not json".into())
+            .unwrap_err()
+            .0
+            .contains("is not JSON"));
+    }
+
+    #[test]
+    fn extracts_with_a_regex() {
+        let tool = tool("extract: {regex: 'Summary: (\\w+)'}");
+        assert_eq!(
+            tool.extract("Summary: Rainy today".into()).unwrap(),
+            "Rainy"
+        );
+        let tool = self::tool("extract: {regex: '\\d+'}");
+        assert_eq!(tool.extract("about 42 degrees".into()).unwrap(), "42");
+        assert!(tool
+            .extract("none".into())
+            .unwrap_err()
+            .0
+            .contains("did not match"));
+        assert_eq!(
+            self::tool("method: GET").extract("body".into()).unwrap(),
+            "body"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        let invalid = |extract: &str| {
+            HttpTool::from_yaml(&format!(
+                "{{id: test, name: Test, description: Test, url: 'http://localhost', {}}}",
+                extract
+            ))
+            .err()
+            .unwrap()
+            .0
+        };
+        assert!(
+            invalid("extract: {regex: '('}").starts_with("Invalid tool 'test' key 'extract.regex'")
+        );
+        assert!(invalid("method: 'G T'").starts_with("Invalid tool 'test' key 'method'"));
+    }
+}
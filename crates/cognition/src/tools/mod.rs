@@ -1,26 +1,124 @@
-use crate::CognitionError;
+use crate::{config::CognitionConfig, parameters::Parameters, CognitionError};
 use async_trait::async_trait;
 use log::debug;
 use reqwest::{header::HeaderMap, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 // Easy access to tools
+pub use http::{Extract, HttpTool, HttpToolDefinition};
 pub use signal::Signal;
 pub use wolfram_alpha::WolframAlpha;
 
+mod http;
 mod signal;
 mod wolfram_alpha;
 
-#[async_trait(?Send)]
-pub trait Tool {
+#[async_trait]
+pub trait Tool: Send + Sync {
     fn id(&self) -> &String;
     fn name(&self) -> &String;
     fn description(&self) -> &String;
-    async fn run(&self, input: &String) -> Result<Option<ToolResponse>, CognitionError>;
+    /// Arguments the model fills in from the conversation before the tool is run. Without
+    /// parameters, the tool is run with `{"input": <user input>}`.
+    fn parameters(&self) -> Option<&Parameters> {
+        None
+    }
+    async fn run(&self, args: &Value) -> Result<Option<ToolResponse>, CognitionError>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ToolResponse {
     pub id: String,
     pub response: String,
 }
+
+// Kind of tool a YAML definition describes, from its `type` key
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum ToolKind {
+    #[default]
+    Http,
+    Signal,
+}
+
+#[derive(Deserialize)]
+struct ToolType {
+    #[serde(default, rename = "type")]
+    kind: ToolKind,
+}
+
+/// Reads a tool from a YAML definition: an `HttpTool`, or a `Signal` with `type: signal`.
+pub fn load_definition(path: &Path) -> Result<Box<dyn Tool>, CognitionError> {
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|err| CognitionError(format!("Unable to read {}: {}", path.display(), err)))?;
+    let parse_error = |err: serde_yaml::Error| {
+        CognitionError(format!(
+            "{}: Unable to parse tool definition: {}",
+            path.display(),
+            err
+        ))
+    };
+    let ToolType { kind } = serde_yaml::from_str(&yaml).map_err(parse_error)?;
+    Ok(match kind {
+        ToolKind::Http => Box::new(
+            HttpTool::from_yaml(&yaml)
+                .map_err(|err| CognitionError(format!("{}: {}", path.display(), err.0)))?,
+        ),
+        ToolKind::Signal => Box::new(serde_yaml::from_str::<Signal>(&yaml).map_err(parse_error)?),
+    })
+}
+
+/// Reads the id of a tool from its YAML definition, without loading the tool.
+pub fn definition_id(path: &Path) -> Result<String, CognitionError> {
+    #[derive(Deserialize)]
+    struct ToolId {
+        id: String,
+    }
+
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|err| CognitionError(format!("Unable to read {}: {}", path.display(), err)))?;
+    let ToolId { id } = serde_yaml::from_str(&yaml).map_err(|err| {
+        CognitionError(format!(
+            "{}: Unable to parse tool definition: {}",
+            path.display(),
+            err
+        ))
+    })?;
+    Ok(id)
+}
+
+/// Loads a tool from each of the given YAML definitions.
+pub fn load_definitions(paths: &[PathBuf]) -> Result<Vec<Box<dyn Tool>>, CognitionError> {
+    paths.iter().map(|path| load_definition(path)).collect()
+}
+
+/// The tools available to decision trees: the definitions listed under `paths.tools`.
+pub fn load_tools(config: &CognitionConfig) -> Result<Vec<Box<dyn Tool>>, CognitionError> {
+    load_definitions(&config.paths.tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn loads_the_kind_of_tool_a_definition_declares() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let tools =
+            load_definitions(&[dir.join("wolfram_tool.yaml"), dir.join("signal_book.yaml")])
+                .unwrap();
+        assert_eq!(tools[0].id(), "wolfram_alpha");
+        assert!(tools[0].parameters().is_some());
+        let response = tools[1].run(&Value::Null).await.unwrap().unwrap();
+        assert_eq!(
+            (response.id.as_str(), response.response.as_str()),
+            ("signal_book", "Beep!")
+        );
+
+        let err = load_definitions(&[dir.join("missing.yaml")]).err().unwrap();
+        assert!(err.0.starts_with("Unable to read"));
+    }
+}
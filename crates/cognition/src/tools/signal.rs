@@ -1,5 +1,7 @@
 use super::*;
 
+/// A `Tool` that responds with a fixed signal, declared in YAML with `type: signal`.
+#[derive(Deserialize, Clone, Debug)]
 pub struct Signal {
     pub id: String,
     pub name: String,
@@ -7,7 +9,7 @@ pub struct Signal {
     pub signal: String,
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 impl Tool for Signal {
     fn id(&self) -> &String {
         &self.id
@@ -21,8 +23,8 @@ impl Tool for Signal {
         &self.description
     }
 
-    async fn run(&self, input: &String) -> Result<Option<ToolResponse>, CognitionError> {
-        debug!("{}: {}", self.id, input);
+    async fn run(&self, args: &Value) -> Result<Option<ToolResponse>, CognitionError> {
+        debug!("{}: {}", self.id, args);
         Ok(Some(ToolResponse {
             id: self.id.clone(),
             response: self.signal.clone(),
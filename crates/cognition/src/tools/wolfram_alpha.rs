@@ -1,4 +1,5 @@
 use super::*;
+use crate::parameters::{Parameter, ParameterType};
 
 pub struct WolframAlpha {
     pub id: String,
@@ -6,6 +7,7 @@ pub struct WolframAlpha {
     pub description: String,
     pub endpoint: Url,
     pub params: HashMap<String, String>,
+    pub parameters: Parameters,
 }
 
 impl WolframAlpha {
@@ -16,11 +18,26 @@ impl WolframAlpha {
             description: "Wolfram Alpha is a computational knowledge engine".to_string(),
             endpoint: "https://api.wolframalpha.com/v1/result".try_into().unwrap(),
             params: vec![("appid".to_string(), app_id)].into_iter().collect(),
+            parameters: Parameters {
+                properties: [(
+                    "input".to_string(),
+                    Parameter {
+                        kind: ParameterType::String,
+                        description: Some(
+                            "The question in plain words, e.g. 'what is 2+2'".to_string(),
+                        ),
+                        allowed: None,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                required: vec!["input".to_string()],
+            },
         }
     }
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 impl Tool for WolframAlpha {
     fn id(&self) -> &String {
         &self.id
@@ -34,15 +51,25 @@ impl Tool for WolframAlpha {
         &self.description
     }
 
-    async fn run(&self, input: &String) -> Result<Option<ToolResponse>, CognitionError> {
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(&self.parameters)
+    }
+
+    async fn run(&self, args: &Value) -> Result<Option<ToolResponse>, CognitionError> {
+        let input = args
+            .get("input")
+            .and_then(Value::as_str)
+            .ok_or_else(|| CognitionError(format!("{}: missing argument 'input'", self.id)))?;
         let client = reqwest::Client::new();
         let headers = HeaderMap::new();
 
         let mut params = self.params.clone();
-        params.insert("i".to_string(), input.clone());
+        params.insert("i".to_string(), input.to_string());
 
         // Create query string from params
-        let query_string = serde_urlencoded::to_string(params).unwrap();
+        let query_string = serde_urlencoded::to_string(params).map_err(|err| {
+            CognitionError(format!("{}: unable to encode the query: {}", self.id, err))
+        })?;
         let url = format!("{}?{}", self.endpoint, query_string);
 
         // Send request to AI tool
@@ -51,16 +78,23 @@ impl Tool for WolframAlpha {
             .headers(headers)
             .send()
             .await
-            .map_err(|err| CognitionError(format!("Failed to send request to tool: {}", err)))?;
+            .map_err(|err| {
+                CognitionError(format!(
+                    "Failed to send request to tool: {}",
+                    err.without_url()
+                ))
+            })?;
 
-        let response = response
-            .text()
-            .await
-            .map_err(|err| CognitionError(format!("Failed to get response text: {}", err)))?;
+        let response = response.text().await.map_err(|err| {
+            CognitionError(format!(
+                "Failed to get response text: {}",
+                err.without_url()
+            ))
+        })?;
         debug!("{}: {}", self.id, response);
         Ok(Some(ToolResponse {
             id: self.id.clone(),
-            response: response,
+            response,
         }))
     }
 }
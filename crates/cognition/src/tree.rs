@@ -0,0 +1,419 @@
+use crate::{engine::Decision, CognitionError};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Separates a sub-tree's namespace from the ids of its nodes, as in `feedback::start`.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// Reads a decision tree from a YAML file, along with the sub-trees its nodes `call`.
+///
+/// The ids of a sub-tree's nodes, and the ids they refer to, are prefixed with the call's
+/// namespace. Sub-trees may call further sub-trees, whose namespaces are nested, e.g.
+/// `checkout::login::start`. A sub-tree called several times under the same namespace is
+/// loaded once. A tree that calls itself, directly or through its sub-trees, is an error.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Decision>, CognitionError> {
+    let mut loader = Loader::default();
+    loader.load(path.as_ref(), None)?;
+    Ok(loader.nodes)
+}
+
+/// Prefixes a node id with a namespace.
+pub fn namespaced(namespace: &str, id: &str) -> String {
+    format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, id)
+}
+
+#[derive(Default)]
+struct Loader {
+    nodes: Vec<Decision>,
+    // File each namespace was loaded from
+    namespaces: HashMap<String, PathBuf>,
+    // Files being loaded, to detect trees that call themselves
+    stack: Vec<PathBuf>,
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path, namespace: Option<&str>) -> Result<(), CognitionError> {
+        let file = canonical(path);
+        if self.stack.contains(&file) {
+            return Err(CognitionError(format!(
+                "{} calls itself through its sub-trees",
+                path.display()
+            )));
+        }
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|err| CognitionError(format!("Unable to read {}: {}", path.display(), err)))?;
+        let mut nodes: Vec<Decision> = serde_yaml::from_str(&yaml).map_err(|err| {
+            CognitionError(format!("Unable to parse {}: {}", path.display(), err))
+        })?;
+
+        if let Some(namespace) = namespace {
+            let ids: HashSet<String> = nodes.iter().map(|node| node.id.clone()).collect();
+            let prefix = |id: &mut String| *id = namespaced(namespace, id);
+            for node in &mut nodes {
+                prefix(&mut node.id);
+                for choice in node.choices.iter_mut().flatten() {
+                    prefix(&mut choice.next_id);
+                }
+                if let Some(else_id) = &mut node.else_id {
+                    prefix(else_id);
+                }
+                // A clarify is either a node id or a question
+                if let Some(clarify) = node.clarify.as_mut().filter(|id| ids.contains(*id)) {
+                    prefix(clarify);
+                }
+                if let Some(call) = &mut node.call {
+                    prefix(&mut call.return_to);
+                }
+            }
+        }
+
+        // Sub-trees are resolved relative to the calling file
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut calls = vec![];
+        for node in &mut nodes {
+            if let Some(call) = &mut node.call {
+                let called = match namespace {
+                    Some(namespace) => namespaced(namespace, &call.namespace()),
+                    None => call.namespace(),
+                };
+                call.namespace = Some(called.clone());
+                calls.push((called, dir.join(&call.tree)));
+            }
+        }
+        self.nodes.extend(nodes);
+
+        self.stack.push(file);
+        for (called, path) in calls {
+            match self.namespaces.get(&called) {
+                Some(loaded) if *loaded == canonical(&path) => {}
+                Some(loaded) => {
+                    return Err(CognitionError(format!(
+                        "Namespace '{}' is used for both {} and {}",
+                        called,
+                        loaded.display(),
+                        path.display()
+                    )))
+                }
+                None => {
+                    self.namespaces.insert(called.clone(), canonical(&path));
+                    self.load(&path, Some(&called))?;
+                }
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+// Files are compared by their canonical path, when they exist
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Position of a node in a `CompiledTree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeIndex(u32);
+
+impl NodeIndex {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The nodes a node leads to, resolved to indices in its `CompiledTree`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Edges {
+    /// Targets of the node's choices, in order, whether or not their guards hold.
+    pub choices: Vec<NodeIndex>,
+    /// Target of the `else` edge.
+    pub else_node: Option<NodeIndex>,
+    /// `start` node of the called sub-tree.
+    pub call_start: Option<NodeIndex>,
+    /// Node the called sub-tree returns to.
+    pub return_to: Option<NodeIndex>,
+    /// Target of a `clarify` that names a node rather than asking a question.
+    pub clarify: Option<NodeIndex>,
+}
+
+/// A decision tree indexed for lookup. Node ids are interned, and the ids each node leads
+/// to (`next_id`s, `else`, a call's sub-tree start and `return_to`, and a `clarify` that names
+/// a node) are resolved to node indices, so the engine moves between nodes without looking up
+/// their ids. Compiling also parses the `when` guards of the choices, so a tree with a
+/// dangling edge or an invalid guard is rejected up front.
+pub struct CompiledTree {
+    nodes: Vec<Decision>,
+    index: HashMap<String, NodeIndex>,
+    edges: Vec<Edges>,
+    // Nodes that a call returns to, the only ones a call stack may hold
+    return_targets: HashSet<NodeIndex>,
+}
+
+impl CompiledTree {
+    /// Indexes the nodes, failing on duplicate ids, edges to unknown nodes and invalid guards.
+    pub fn new(mut nodes: Vec<Decision>) -> Result<Self, CognitionError> {
+        if nodes.len() > u32::MAX as usize {
+            return Err(CognitionError(format!(
+                "Decision tree has too many nodes: {}",
+                nodes.len()
+            )));
+        }
+        let mut index = HashMap::with_capacity(nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            if index.insert(node.id.clone(), NodeIndex(i as u32)).is_some() {
+                return Err(CognitionError(format!("Duplicate node id '{}'", node.id)));
+            }
+        }
+
+        let mut edges = Vec::with_capacity(nodes.len());
+        let mut return_targets = HashSet::new();
+        for node in &mut nodes {
+            let resolve = |id: &str| {
+                index.get(id).copied().ok_or_else(|| {
+                    CognitionError(format!("Node '{}' leads to unknown node '{}'", node.id, id))
+                })
+            };
+            let node_edges = Edges {
+                choices: node
+                    .all_choices()
+                    .iter()
+                    .map(|choice| resolve(&choice.next_id))
+                    .collect::<Result<_, _>>()?,
+                else_node: node.else_id.as_deref().map(resolve).transpose()?,
+                call_start: node
+                    .call
+                    .as_ref()
+                    .map(|call| resolve(&call.start_id()))
+                    .transpose()?,
+                return_to: node
+                    .call
+                    .as_ref()
+                    .map(|call| resolve(&call.return_to))
+                    .transpose()?,
+                // A clarify that is not a node id is a question
+                clarify: node
+                    .clarify
+                    .as_deref()
+                    .and_then(|id| index.get(id))
+                    .copied(),
+            };
+            return_targets.extend(node_edges.return_to);
+            edges.push(node_edges);
+
+            for choice in node.choices.iter_mut().flatten() {
+                choice.compile_guard().map_err(|err| {
+                    CognitionError(format!("Node '{}' has an invalid guard: {}", node.id, err))
+                })?;
+            }
+        }
+
+        Ok(Self {
+            nodes,
+            index,
+            edges,
+            return_targets,
+        })
+    }
+
+    /// Reads a decision tree and its sub-trees (see `load`) and compiles it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CognitionError> {
+        let path = path.as_ref();
+        Self::new(load(path)?)
+            .map_err(|err| CognitionError(format!("{}: {}", path.display(), err.0)))
+    }
+
+    /// The node with the given id.
+    pub fn get(&self, id: &str) -> Option<&Decision> {
+        self.index_of(id).map(|index| self.node(index))
+    }
+
+    pub fn index_of(&self, id: &str) -> Option<NodeIndex> {
+        self.index.get(id).copied()
+    }
+
+    pub fn node(&self, index: NodeIndex) -> &Decision {
+        &self.nodes[index.index()]
+    }
+
+    /// The nodes the given node leads to.
+    pub fn edges(&self, index: NodeIndex) -> &Edges {
+        &self.edges[index.index()]
+    }
+
+    /// Whether a call returns to the given node.
+    pub fn is_return_target(&self, index: NodeIndex) -> bool {
+        self.return_targets.contains(&index)
+    }
+
+    /// All nodes, in the order they were loaded.
+    pub fn nodes(&self) -> &[Decision] {
+        &self.nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(yaml: &str) -> Result<CompiledTree, CognitionError> {
+        CompiledTree::new(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    // Writes the files of a test tree to a directory of its own
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cognition_tree_test_{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, yaml) in files {
+            std::fs::write(dir.join(file), yaml).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn resolves_edges_to_indices() {
+        let tree = compile(
+            r#"
+- id: start
+  clarify: "Which one?"
+  choices:
+    - {choice: "A", next_id: a}
+    - {choice: "B", next_id: b, when: "vars.b"}
+  else: a
+- id: a
+  clarify: start
+  choices: []
+- id: b
+  call: {tree: sub.yaml, return_to: a}
+- id: sub::start
+  choices: []
+"#,
+        )
+        .unwrap();
+        let index = |id| tree.index_of(id).unwrap();
+        assert_eq!(tree.node(index("b")).id, "b");
+        assert_eq!(
+            *tree.edges(index("start")),
+            Edges {
+                choices: vec![index("a"), index("b")],
+                else_node: Some(index("a")),
+                ..Edges::default()
+            }
+        );
+        assert_eq!(tree.edges(index("a")).clarify, Some(index("start")));
+        assert_eq!(tree.edges(index("b")).call_start, Some(index("sub::start")));
+        assert_eq!(tree.edges(index("b")).return_to, Some(index("a")));
+        assert!(tree.is_return_target(index("a")));
+        assert!(!tree.is_return_target(index("start")));
+        assert!(tree.get("missing").is_none());
+    }
+
+    #[test]
+    fn rejects_a_dangling_next_id() {
+        let err = compile(
+            r#"
+- id: start
+  choices:
+    - {choice: "A", next_id: missing}
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.0, "Node 'start' leads to unknown node 'missing'");
+
+        let err = compile("- {id: start, call: {tree: sub.yaml, return_to: start}}")
+            .err()
+            .unwrap();
+        assert_eq!(err.0, "Node 'start' leads to unknown node 'sub::start'");
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let err = compile("- {id: start, choices: []}\n- {id: start, choices: []}")
+            .err()
+            .unwrap();
+        assert_eq!(err.0, "Duplicate node id 'start'");
+    }
+
+    #[test]
+    fn rejects_an_invalid_guard() {
+        let err = compile(
+            r#"
+- id: start
+  choices:
+    - {choice: "A", next_id: start, when: "vars.member =="}
+"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.0.starts_with("Node 'start' has an invalid guard: "));
+    }
+
+    #[test]
+    fn namespaces_the_nodes_of_sub_trees() {
+        let dir = write_files(
+            "namespaces",
+            &[
+                (
+                    "main.yaml",
+                    "- {id: start, call: {tree: feedback.yaml, return_to: done}}\n\
+                     - {id: done, choices: []}",
+                ),
+                (
+                    "feedback.yaml",
+                    r#"
+- id: start
+  clarify: start
+  choices:
+    - {choice: "Rate", next_id: rate}
+  else: start
+- id: rate
+  call: {tree: survey.yaml, return_to: start, namespace: poll}
+"#,
+                ),
+                ("survey.yaml", "- {id: start, clarify: Why?, choices: []}"),
+            ],
+        );
+        let nodes = load(dir.join("main.yaml")).unwrap();
+        let ids: Vec<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "start",
+                "done",
+                "feedback::start",
+                "feedback::rate",
+                "feedback::poll::start"
+            ]
+        );
+        let feedback = &nodes[2];
+        assert_eq!(feedback.all_choices()[0].next_id, "feedback::rate");
+        assert_eq!(feedback.else_id.as_deref(), Some("feedback::start"));
+        assert_eq!(feedback.clarify.as_deref(), Some("feedback::start"));
+        let call = nodes[3].call.as_ref().unwrap();
+        assert_eq!(call.return_to, "feedback::start");
+        assert_eq!(call.start_id(), "feedback::poll::start");
+        // A clarify that is a question is left as it is
+        assert_eq!(nodes[4].clarify.as_deref(), Some("Why?"));
+        assert!(CompiledTree::new(nodes).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_tree_that_calls_itself() {
+        let dir = write_files(
+            "recursive",
+            &[(
+                "main.yaml",
+                "- {id: start, call: {tree: main.yaml, return_to: start}}",
+            )],
+        );
+        let err = load(dir.join("main.yaml")).err().unwrap();
+        assert!(err.0.ends_with("calls itself through its sub-trees"));
+    }
+}
@@ -0,0 +1,629 @@
+use crate::{
+    engine::Decision,
+    expression::Expression,
+    tools,
+    tree::{self, NAMESPACE_SEPARATOR},
+    DecisionPromptTemplate,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+/// Id of the node every conversation starts at.
+pub const START_ID: &str = "start";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The YAML could not be parsed into decision nodes.
+    Parse,
+    /// A `next_id` names a node that does not exist.
+    DanglingNextId,
+    /// Two or more nodes share the same id.
+    DuplicateId,
+    /// There is no `start` node.
+    MissingStart,
+    /// The node cannot be reached from `start`.
+    Unreachable,
+    /// No terminal node (one without choices or `else`) can be reached from the node.
+    NoPathToTerminal,
+    /// The node uses a tool that is not registered.
+    UnknownTool,
+    /// Two choices of the same node have the same text.
+    DuplicateChoice,
+    /// The decision prompt template lacks a placeholder.
+    MissingPlaceholder,
+    /// A choice's `when` guard does not parse.
+    InvalidGuard,
+    /// A sub-tree named by a `call` cannot be read or parsed, or has no `start` node.
+    InvalidCall,
+    /// Every choice of the node has a `when` guard, and there is neither an `else` nor a slot
+    /// that the answer can fill, so the conversation can get stuck at the node.
+    AllChoicesGuarded,
+    /// A tool definition cannot be read or parsed.
+    InvalidTool,
+}
+
+/// A problem found in a decision tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    /// Node the problem was found in, if any.
+    pub node_id: Option<String>,
+    /// One-based line in the YAML source, if known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, severity: Severity, message: String) -> Self {
+        Self {
+            kind,
+            severity,
+            node_id: None,
+            line: None,
+            message,
+        }
+    }
+
+    fn node(mut self, node_id: &str) -> Self {
+        self.node_id = Some(node_id.to_owned());
+        self
+    }
+
+    fn line(mut self, line: Option<usize>) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error")?,
+            Severity::Warning => write!(f, "warning")?,
+        }
+        if let Some(line) = self.line {
+            write!(f, ": line {}", line)?;
+        }
+        if let Some(node_id) = &self.node_id {
+            write!(f, ": node '{}'", node_id)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Reads the ids of the tools defined in the given YAML files, for `validate_file` and
+/// `lint_file`. A definition that cannot be read or parsed is reported rather than failing,
+/// and nodes using its tool are then reported as using an unknown tool.
+pub fn tool_ids(paths: &[PathBuf]) -> (Vec<String>, Vec<Diagnostic>) {
+    let mut ids = vec![];
+    let mut diagnostics = vec![];
+    for path in paths {
+        match tools::definition_id(path) {
+            Ok(id) => ids.push(id),
+            Err(err) => diagnostics.push(Diagnostic::new(
+                DiagnosticKind::InvalidTool,
+                Severity::Warning,
+                format!("Unable to load tool definition: {}", err.0),
+            )),
+        }
+    }
+    (ids, diagnostics)
+}
+
+/// Parses a decision tree from YAML and validates it, reporting YAML line numbers.
+///
+/// `tool_ids` are the ids of the registered tools; nodes using any other tool are reported.
+pub fn validate_tree(source: &str, tool_ids: &[&str]) -> Vec<Diagnostic> {
+    let nodes: Vec<Decision> = match serde_yaml::from_str(source) {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            let line = err.location().map(|location| location.line());
+            return vec![Diagnostic::new(
+                DiagnosticKind::Parse,
+                Severity::Error,
+                format!("Unable to parse decision tree: {}", err),
+            )
+            .line(line)];
+        }
+    };
+    validate(&nodes, tool_ids, &SourceLines::new(source, nodes.len()))
+}
+
+/// Validates a decision tree like `validate_tree`, and also warns about duplicate
+/// choice texts within a node.
+pub fn lint_tree(source: &str, tool_ids: &[&str]) -> Vec<Diagnostic> {
+    let mut diagnostics = validate_tree(source, tool_ids);
+    if let Ok(nodes) = serde_yaml::from_str::<Vec<Decision>>(source) {
+        let lines = SourceLines::new(source, nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            let mut seen: HashMap<String, usize> = HashMap::new();
+            for choice in node.all_choices() {
+                let text = choice.text.trim();
+                let count = seen.entry(text.to_lowercase()).or_default();
+                *count += 1;
+                if *count == 2 {
+                    diagnostics.push(
+                        Diagnostic::new(
+                            DiagnosticKind::DuplicateChoice,
+                            Severity::Warning,
+                            format!("Duplicate choice '{}'", text),
+                        )
+                        .node(&node.id)
+                        .line(lines.find_nth(i, "choice", text, 1)),
+                    );
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Reads a decision tree file and validates it like `validate_tree`, along with the sub-trees
+/// it calls (see `tree::load`). Diagnostics in sub-trees carry no line numbers.
+pub fn validate_file(path: &Path, tool_ids: &[&str]) -> Vec<Diagnostic> {
+    check_file(path, tool_ids, validate_tree)
+}
+
+/// Reads a decision tree file and lints it like `lint_tree`. The sub-trees it calls are
+/// validated like `validate_file`.
+pub fn lint_file(path: &Path, tool_ids: &[&str]) -> Vec<Diagnostic> {
+    check_file(path, tool_ids, lint_tree)
+}
+
+fn check_file(
+    path: &Path,
+    tool_ids: &[&str],
+    check: fn(&str, &[&str]) -> Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = match std::fs::read_to_string(path) {
+        Ok(source) => check(&source, tool_ids),
+        Err(err) => {
+            return vec![Diagnostic::new(
+                DiagnosticKind::Parse,
+                Severity::Error,
+                format!("Unable to read {}: {}", path.display(), err),
+            )]
+        }
+    };
+    if diagnostics.iter().any(|d| d.kind == DiagnosticKind::Parse) {
+        return diagnostics;
+    }
+
+    // The file itself is already checked, so only keep what is found in its sub-trees
+    match tree::load(path) {
+        Ok(nodes) => {
+            // Calls are only resolved once the sub-trees are loaded
+            let ids: HashSet<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+            for node in &nodes {
+                if let Some(call) = &node.call {
+                    if !ids.contains(call.start_id().as_str()) {
+                        diagnostics.push(
+                            Diagnostic::new(
+                                DiagnosticKind::InvalidCall,
+                                Severity::Error,
+                                format!(
+                                    "Sub-tree '{}' has no '{}' node",
+                                    call.tree.display(),
+                                    START_ID
+                                ),
+                            )
+                            .node(&node.id),
+                        );
+                    }
+                }
+            }
+            diagnostics.extend(
+                validate_nodes(&nodes, tool_ids)
+                    .into_iter()
+                    .filter(|diagnostic| {
+                        diagnostic
+                            .node_id
+                            .as_deref()
+                            .is_some_and(|id| id.contains(NAMESPACE_SEPARATOR))
+                    }),
+            )
+        }
+        Err(err) => diagnostics.push(Diagnostic::new(
+            DiagnosticKind::InvalidCall,
+            Severity::Error,
+            err.0,
+        )),
+    }
+    diagnostics
+}
+
+/// Checks that the decision prompt template contains every placeholder.
+pub fn lint_template(template: &DecisionPromptTemplate) -> Vec<Diagnostic> {
+    template
+        .missing_placeholders()
+        .into_iter()
+        .map(|placeholder| {
+            Diagnostic::new(
+                DiagnosticKind::MissingPlaceholder,
+                Severity::Error,
+                format!("Template is missing the {} placeholder", placeholder),
+            )
+        })
+        .collect()
+}
+
+/// Validates already parsed decision nodes. Diagnostics carry no line numbers.
+pub fn validate_nodes(nodes: &[Decision], tool_ids: &[&str]) -> Vec<Diagnostic> {
+    validate(nodes, tool_ids, &SourceLines::default())
+}
+
+fn validate(nodes: &[Decision], tool_ids: &[&str], lines: &SourceLines) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    // Index nodes by id, reporting duplicates
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(first) = index.get(node.id.as_str()) {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticKind::DuplicateId,
+                    Severity::Error,
+                    format!(
+                        "Duplicate node id, first defined{}",
+                        lines
+                            .node(*first)
+                            .map(|line| format!(" at line {}", line))
+                            .unwrap_or_default()
+                    ),
+                )
+                .node(&node.id)
+                .line(lines.node(i)),
+            );
+        } else {
+            index.insert(&node.id, i);
+        }
+    }
+
+    if !index.contains_key(START_ID) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::MissingStart,
+            Severity::Error,
+            format!("Missing '{}' node", START_ID),
+        ));
+    }
+
+    // Resolve edges, reporting dangling ones
+    let mut edges: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for next_id in node.next_ids() {
+            match index.get(next_id) {
+                Some(next) => edges[i].push(*next),
+                None => diagnostics.push(
+                    Diagnostic::new(
+                        DiagnosticKind::DanglingNextId,
+                        Severity::Error,
+                        format!("next_id '{}' does not match any node", next_id),
+                    )
+                    .node(&node.id)
+                    .line(lines.find(i, edge_key(node, next_id), next_id)),
+                ),
+            }
+        }
+        // A clarify that names a node is an edge too
+        if let Some(next) = node.clarify.as_deref().and_then(|id| index.get(id)) {
+            edges[i].push(*next);
+        }
+        // So is a call, once its sub-tree is loaded
+        if let Some(next) = node
+            .call
+            .as_ref()
+            .and_then(|call| index.get(&*call.start_id()))
+        {
+            edges[i].push(*next);
+        }
+
+        for when in node
+            .all_choices()
+            .iter()
+            .filter_map(|choice| choice.when.as_deref())
+        {
+            if let Err(err) = Expression::parse(when) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        DiagnosticKind::InvalidGuard,
+                        Severity::Error,
+                        format!("Invalid guard '{}': {}", when, err),
+                    )
+                    .node(&node.id)
+                    .line(lines.find(i, "when", when)),
+                );
+            }
+        }
+
+        // The answer to a node with slots can make its guards hold
+        let choices = node.all_choices();
+        let all_guarded = choices.iter().all(|choice| choice.when.is_some());
+        let has_slots = node.slots.as_ref().is_some_and(|slots| !slots.is_empty());
+        if !choices.is_empty() && all_guarded && node.else_id.is_none() && !has_slots {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticKind::AllChoicesGuarded,
+                    Severity::Warning,
+                    "Every choice has a guard and there is no 'else', so the node may have no \
+                     available choice"
+                        .to_owned(),
+                )
+                .node(&node.id)
+                .line(lines.node(i)),
+            );
+        }
+
+        if let Some(tool_id) = &node.tool {
+            if !tool_ids.contains(&tool_id.as_str()) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        DiagnosticKind::UnknownTool,
+                        Severity::Error,
+                        format!("Tool '{}' is not registered", tool_id),
+                    )
+                    .node(&node.id)
+                    .line(lines.find(i, "tool", tool_id)),
+                );
+            }
+        }
+    }
+
+    // Nodes reachable from start
+    if let Some(start) = index.get(START_ID) {
+        let reachable = reach(&[*start], &edges);
+        for (i, node) in nodes.iter().enumerate() {
+            if !reachable.contains(&i) && index.get(node.id.as_str()) == Some(&i) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        DiagnosticKind::Unreachable,
+                        Severity::Warning,
+                        format!("Node cannot be reached from '{}'", START_ID),
+                    )
+                    .node(&node.id)
+                    .line(lines.node(i)),
+                );
+            }
+        }
+    }
+
+    // Nodes that can reach a terminal node, walking the edges backwards
+    let mut reverse: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+    for (i, next) in edges.iter().enumerate() {
+        for next in next {
+            reverse[*next].push(i);
+        }
+    }
+    let terminals: Vec<usize> = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.is_terminal())
+        .map(|(i, _)| i)
+        .collect();
+    let finishing = reach(&terminals, &reverse);
+    for (i, node) in nodes.iter().enumerate() {
+        if !finishing.contains(&i) && index.get(node.id.as_str()) == Some(&i) {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticKind::NoPathToTerminal,
+                    Severity::Warning,
+                    "No path from this node to a node without choices".to_owned(),
+                )
+                .node(&node.id)
+                .line(lines.node(i)),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+// YAML key of the edge to the given node: a choice's `next_id`, the node's `else` or
+// its call's `return_to`
+fn edge_key(node: &Decision, next_id: &str) -> &'static str {
+    if node
+        .all_choices()
+        .iter()
+        .any(|choice| choice.next_id() == next_id)
+    {
+        "next_id"
+    } else if node.else_id.as_deref() == Some(next_id) {
+        "else"
+    } else {
+        "return_to"
+    }
+}
+
+// Breadth-first search from the given nodes
+fn reach(from: &[usize], edges: &[Vec<usize>]) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = from.iter().copied().collect();
+    let mut queue: VecDeque<usize> = from.iter().copied().collect();
+    while let Some(i) = queue.pop_front() {
+        for next in &edges[i] {
+            if seen.insert(*next) {
+                queue.push_back(*next);
+            }
+        }
+    }
+    seen
+}
+
+// Line numbers of the top-level list items in the YAML source
+#[derive(Default)]
+struct SourceLines {
+    lines: Vec<String>,
+    starts: Vec<usize>,
+}
+
+impl SourceLines {
+    fn new(source: &str, count: usize) -> Self {
+        let lines: Vec<String> = source.lines().map(str::to_owned).collect();
+        let indent = lines
+            .iter()
+            .filter(|line| line.trim_start().starts_with("- "))
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let starts: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                line.len() > indent
+                    && line[..indent].trim().is_empty()
+                    && line[indent..].starts_with("- ")
+            })
+            .map(|(i, _)| i)
+            .collect();
+        // Fall back to no line numbers if the layout is not a plain list
+        if starts.len() != count {
+            return Self::default();
+        }
+        Self { lines, starts }
+    }
+
+    // One-based line of the node at `index`
+    fn node(&self, index: usize) -> Option<usize> {
+        self.starts.get(index).map(|line| line + 1)
+    }
+
+    // One-based line of `key: value` within the node at `index`
+    fn find(&self, index: usize, key: &str, value: &str) -> Option<usize> {
+        self.find_nth(index, key, value, 0)
+    }
+
+    // One-based line of the `nth` occurrence of `key: value` within the node at `index`
+    fn find_nth(&self, index: usize, key: &str, value: &str, nth: usize) -> Option<usize> {
+        let start = *self.starts.get(index)?;
+        let end = self
+            .starts
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.lines.len());
+        (start..end)
+            .filter(|i| {
+                let line = self.lines[*i].trim_start().trim_start_matches("- ");
+                line.strip_prefix(key)
+                    .and_then(|rest| rest.trim_start().strip_prefix(':'))
+                    .map(|rest| rest.trim().trim_matches(['"', '\'']) == value)
+                    .unwrap_or(false)
+            })
+            .nth(nth)
+            .map(|i| i + 1)
+            .or_else(|| self.node(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = "\
+# Comment
+- id: start
+  text: \"Hello\"
+  choices:
+    - choice: \"Buy\"
+      next_id: \"buy\"
+    - choice: \"Buy\"
+      next_id: \"missing\"
+
+- id: buy
+  tool: unknown
+  choices: []
+";
+
+    #[test]
+    fn finds_the_lines_of_nodes_and_keys() {
+        let lines = SourceLines::new(TREE, 2);
+        assert_eq!(lines.node(0), Some(2));
+        assert_eq!(lines.node(1), Some(10));
+        assert_eq!(lines.node(2), None);
+        assert_eq!(lines.find(0, "next_id", "missing"), Some(8));
+        assert_eq!(lines.find_nth(0, "choice", "Buy", 1), Some(7));
+        assert_eq!(lines.find(1, "tool", "unknown"), Some(11));
+        // Keys outside the node fall back to the node's line
+        assert_eq!(lines.find(1, "next_id", "buy"), Some(10));
+    }
+
+    #[test]
+    fn finds_the_lines_of_indented_lists() {
+        let source = "  - id: start\n    choices: []\n  - id: end\n    choices: []\n";
+        let lines = SourceLines::new(source, 2);
+        assert_eq!(lines.node(1), Some(3));
+    }
+
+    #[test]
+    fn leaves_out_line_numbers_of_other_layouts() {
+        let source = "[{id: start, choices: []}]";
+        let lines = SourceLines::new(source, 1);
+        assert_eq!(lines.node(0), None);
+        assert_eq!(lines.find(0, "id", "start"), None);
+    }
+
+    #[test]
+    fn reports_diagnostics_with_line_numbers() {
+        let diagnostics = lint_tree(TREE, &[]);
+        let lines: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.kind, diagnostic.line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (DiagnosticKind::DanglingNextId, Some(8)),
+                (DiagnosticKind::UnknownTool, Some(11)),
+                (DiagnosticKind::DuplicateChoice, Some(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_nodes_whose_choices_are_all_guarded() {
+        let source = "\
+- id: start
+  choices:
+    - {choice: Member, next_id: end, when: vars.member}
+- id: other
+  choices:
+    - {choice: Member, next_id: end, when: vars.member}
+  else: end
+- id: rated
+  slots: {rating: integer}
+  choices:
+    - {choice: Good, next_id: end, when: vars.rating > 3}
+- id: end
+  choices: []
+";
+        let diagnostics = validate_tree(source, &[]);
+        let kinds: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.kind, diagnostic.node_id.as_deref()))
+            .collect();
+        assert!(kinds.contains(&(DiagnosticKind::AllChoicesGuarded, Some("start"))));
+        assert!(!kinds.contains(&(DiagnosticKind::AllChoicesGuarded, Some("other"))));
+        assert!(!kinds.contains(&(DiagnosticKind::AllChoicesGuarded, Some("rated"))));
+        assert!(diagnostics.iter().all(|diagnostic| !diagnostic.is_error()));
+    }
+
+    #[test]
+    fn reports_unreadable_tool_definitions() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let (ids, diagnostics) =
+            tool_ids(&[dir.join("wolfram_tool.yaml"), dir.join("missing_tool.yaml")]);
+        assert_eq!(ids, ["wolfram_alpha"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::InvalidTool);
+        assert!(!diagnostics[0].is_error());
+    }
+}